@@ -0,0 +1,127 @@
+// Benchmarks of piece cell lookups and of the code built on them: collision checks,
+// bot placement search and headless bot-vs-bot simulation. Prints operations per second,
+// compare runs before and after a change on the same machine.
+//
+// Usage: cargo bench -p tetris-engine [-- --rounds N]
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use tetris_engine::{
+    bot, Bot, BotDifficulty, CellType, PieceSet, PlayerSide, Rotation, RuleSet, TetrisPair,
+    Tetromino,
+};
+
+const COLS: usize = 10;
+const ROWS: usize = 20;
+
+// Field with filled bottom half and a hole in each row, like a typical mid-game board
+fn half_filled_field() -> Vec<Vec<CellType>> {
+    (0..ROWS)
+        .map(|y| {
+            (0..COLS)
+                .map(|x| {
+                    if y >= ROWS / 2 && x != y % COLS {
+                        CellType::Blasted
+                    } else {
+                        CellType::Empty
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Run `f` `rounds` times, `f` returns number of operations done
+fn bench(name: &str, rounds: usize, mut f: impl FnMut() -> usize) {
+    let started = Instant::now();
+    let operations: usize = (0..rounds).map(|_| f()).sum();
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "{:<24} {:>10} ops in {:.2} s ({:.0} ops/s)",
+        name,
+        operations,
+        elapsed,
+        operations as f64 / elapsed.max(f64::EPSILON)
+    );
+}
+
+// Every piece of the set in every rotation and position of the field
+fn intersects(pieces: &PieceSet, field: &Vec<Vec<CellType>>) -> usize {
+    let mut checks = 0;
+    for tetromino_type in pieces.get_pieces() {
+        for rotation in Rotation::ALL {
+            for y in 0..ROWS as isize {
+                for x in 0..COLS as isize {
+                    let tetromino = Tetromino::new(*tetromino_type, rotation, x, y);
+                    black_box(tetromino.intersects(black_box(field), &[]));
+                    checks += 1;
+                }
+            }
+        }
+    }
+    checks
+}
+
+// Best placement of every piece of the set
+fn placements(pieces: &PieceSet, field: &Vec<Vec<CellType>>) -> usize {
+    for tetromino_type in pieces.get_pieces() {
+        let tetromino = Tetromino::spawn(*tetromino_type, 0, COLS, 0);
+        black_box(bot::best_placement(black_box(field), &tetromino));
+    }
+    pieces.get_pieces().len()
+}
+
+// Bot-vs-bot match of the tournament, returns number of steps
+fn simulation(seed: u64, max_steps: usize) -> usize {
+    let mut pair = TetrisPair::with_seed(&RuleSet::default(), seed);
+    pair.start();
+    let mut bots = [
+        (PlayerSide::A, Bot::with_seed(BotDifficulty::HARD, seed + 1)),
+        (PlayerSide::B, Bot::with_seed(BotDifficulty::HARD, seed + 2)),
+    ];
+    let mut steps = 0;
+    while steps < max_steps && !pair.is_game_over() {
+        for (side, bot) in bots.iter_mut() {
+            let (tetris, _) = pair.get_player_tetris(*side);
+            if let Some(action) = bot.think(tetris) {
+                pair.add_player_action(*side, action);
+            }
+            pair.step_player(*side);
+        }
+        steps += 1;
+    }
+    steps
+}
+
+fn main() {
+    // Cargo passes --bench to benchmarks without harness
+    let mut rounds = 100;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--rounds" {
+            rounds = args.next().and_then(|v| v.parse().ok()).unwrap_or(rounds);
+        }
+    }
+
+    let field = half_filled_field();
+    let tetrominoes = PieceSet::tetrominoes();
+    let pentominoes = PieceSet::pentominoes();
+    bench("intersects tetrominoes", rounds * 20, || {
+        intersects(&tetrominoes, &field)
+    });
+    bench("intersects pentominoes", rounds * 20, || {
+        intersects(&pentominoes, &field)
+    });
+    bench("bot tetrominoes", rounds * 20, || {
+        placements(&tetrominoes, &field)
+    });
+    bench("bot pentominoes", rounds * 20, || {
+        placements(&pentominoes, &field)
+    });
+    let mut seed = 0;
+    bench("simulation steps", rounds, || {
+        seed += 3;
+        simulation(seed, 20_000)
+    });
+}
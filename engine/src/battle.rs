@@ -0,0 +1,390 @@
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    group_matches::GroupField,
+    lifecycle::{MatchPhase, START_COUNTDOWN_STEPS},
+    rules::RuleSet,
+    tetris::{Action, CellType, StepResult, SystemAction, Tetris},
+};
+
+// Most boards in one battle
+pub const MAX_PLAYERS: usize = 16;
+
+// How player chooses the opponent who receives garbage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Targeting {
+    // Random opponent for each attack
+    #[default]
+    Random,
+    // All opponents targeting the player, random one if nobody does
+    Attackers,
+    // Opponent closest to be knocked out, i.e. with the highest stack
+    Kos,
+    // Opponent with the most knockouts
+    Badges,
+}
+
+impl std::str::FromStr for Targeting {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Targeting::Random),
+            "attackers" => Ok(Targeting::Attackers),
+            "kos" => Ok(Targeting::Kos),
+            "badges" => Ok(Targeting::Badges),
+            _ => Err(()),
+        }
+    }
+}
+
+struct BattlePlayer {
+    tetris: Tetris,
+    stepped: bool,
+    targeting: Targeting,
+    // Opponent who received the last attack
+    target: Option<usize>,
+    // Opponent who sent the last garbage, credited with knockout
+    last_attacker: Option<usize>,
+    // Opponents knocked out by the player
+    kos: usize,
+    // Final place, 1 for the winner. None while the player is alive
+    placement: Option<usize>,
+}
+
+// Player's state in the battle besides the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BattleStanding {
+    pub targeting: Targeting,
+    pub target: Option<usize>,
+    pub kos: usize,
+    pub placement: Option<usize>,
+    // Other players were knocked out in the same step and share the placement
+    pub tied: bool,
+}
+
+//
+// Battle royale of up to MAX_PLAYERS boards. Removed lines are sent as garbage to opponents
+// chosen by player's targeting strategy. Player whose board is topped out is knocked out,
+// the last one standing wins. Boards are stepped when all players who are still alive
+// have called step, same as in TetrisPair
+//
+pub struct Battle {
+    players: Vec<BattlePlayer>,
+    phase: MatchPhase,
+    // Players still in the game
+    alive: usize,
+    rng: ChaCha12Rng,
+}
+
+impl Battle {
+    pub fn new(rules: &RuleSet, players: usize) -> Battle {
+        let players = players.min(MAX_PLAYERS);
+        Battle {
+            players: (0..players)
+                .map(|_| BattlePlayer {
+                    tetris: Tetris::new(rules),
+                    stepped: false,
+                    targeting: Targeting::default(),
+                    target: None,
+                    last_attacker: None,
+                    kos: 0,
+                    placement: None,
+                })
+                .collect(),
+            // Battles have no ready check, countdown starts at once
+            phase: MatchPhase::Countdown(START_COUNTDOWN_STEPS),
+            alive: players,
+            rng: ChaCha12Rng::from_entropy(),
+        }
+    }
+
+    // Send garbage for one removed line to the targets of the attacker
+    fn attack(&mut self, attacker: usize) {
+        let targets = self.choose_targets(attacker);
+        self.players[attacker].target = targets.last().copied();
+        for target in targets {
+            let target_player = &mut self.players[target];
+            for _ in 0..target_player.tetris.get_rules().garbage.rows_per_line {
+                target_player
+                    .tetris
+                    .add_system_action(SystemAction::BottomRefill);
+            }
+            target_player.last_attacker = Some(attacker);
+        }
+    }
+
+    fn choose_targets(&mut self, attacker: usize) -> Vec<usize> {
+        let opponents: Vec<usize> = (0..self.players.len())
+            .filter(|i| *i != attacker && self.players[*i].placement.is_none())
+            .collect();
+        let chosen = match self.players[attacker].targeting {
+            Targeting::Random => None,
+            Targeting::Attackers => {
+                let attackers: Vec<usize> = opponents
+                    .iter()
+                    .copied()
+                    .filter(|i| self.players[*i].target == Some(attacker))
+                    .collect();
+                (!attackers.is_empty()).then_some(attackers)
+            }
+            Targeting::Kos => opponents
+                .iter()
+                .copied()
+                .max_by_key(|i| stack_height(&self.players[*i].tetris))
+                .map(|i| vec![i]),
+            Targeting::Badges => opponents
+                .iter()
+                .copied()
+                .filter(|i| self.players[*i].kos > 0)
+                .max_by_key(|i| self.players[*i].kos)
+                .map(|i| vec![i]),
+        };
+        chosen.unwrap_or_else(|| {
+            opponents
+                .choose(&mut self.rng)
+                .map(|i| vec![*i])
+                .unwrap_or_default()
+        })
+    }
+
+    // Players knocked out in the same step share the worst place among those still alive,
+    // the last attacker of each gets the KO. If nobody is left, they share the first place
+    // as a draw
+    fn knock_out(&mut self, knocked: &[usize]) {
+        if knocked.is_empty() {
+            return;
+        }
+        let placement = self.alive + 1 - knocked.len();
+        for player in knocked {
+            self.players[*player].placement = Some(placement);
+        }
+        self.alive -= knocked.len();
+        for player in knocked {
+            if let Some(attacker) = self.players[*player].last_attacker {
+                if self.players[attacker].placement.is_none() {
+                    self.players[attacker].kos += 1;
+                }
+            }
+        }
+        // The only player left wasn't knocked out in this step, so the board isn't game over
+        if self.alive <= 1 {
+            if let Some(winner) = self.players.iter_mut().find(|p| p.placement.is_none()) {
+                winner.placement = Some(1);
+            }
+            self.alive = 0;
+            self.phase = MatchPhase::Finished;
+        }
+    }
+
+    pub fn set_targeting(&mut self, player: usize, targeting: Targeting) {
+        if let Some(battle_player) = self.players.get_mut(player) {
+            battle_player.targeting = targeting;
+        }
+    }
+
+    pub fn get_standing(&self, player: usize) -> Option<BattleStanding> {
+        self.players.get(player).map(|p| BattleStanding {
+            targeting: p.targeting,
+            target: p.target,
+            kos: p.kos,
+            placement: p.placement,
+            tied: p.placement.is_some()
+                && self
+                    .players
+                    .iter()
+                    .filter(|other| other.placement == p.placement)
+                    .count()
+                    > 1,
+        })
+    }
+
+    // Players ordered by placement, players still alive go first
+    pub fn get_ranking(&self) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..self.players.len()).collect();
+        ranking.sort_by_key(|i| self.players[*i].placement.unwrap_or(0));
+        ranking
+    }
+
+    pub fn get_players_left(&self) -> usize {
+        self.players
+            .iter()
+            .filter(|p| p.placement.is_none())
+            .count()
+    }
+
+    pub fn get_phase(&self) -> MatchPhase {
+        self.phase
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.phase == MatchPhase::Finished
+    }
+}
+
+// Rows from the bottom to the highest filled cell
+fn stack_height(tetris: &Tetris) -> usize {
+    let field = tetris.get_field();
+    field
+        .iter()
+        .position(|row| row.iter().any(|cell| *cell != CellType::Empty))
+        .map_or(0, |top| field.len() - top)
+}
+
+impl GroupField for Battle {
+    fn players(&self) -> usize {
+        self.players.len()
+    }
+
+    fn step_player(&mut self, player: usize) {
+        if let Some(battle_player) = self.players.get_mut(player) {
+            battle_player.stepped = true;
+        }
+        let waiting = self
+            .players
+            .iter()
+            .any(|p| p.placement.is_none() && !p.stepped);
+        if waiting {
+            return;
+        }
+        for battle_player in self.players.iter_mut() {
+            battle_player.stepped = false;
+        }
+        if !self.phase.step_countdown() {
+            return;
+        }
+        for attacker in 0..self.players.len() {
+            if self.players[attacker].placement.is_some() {
+                continue;
+            }
+            if self.players[attacker].tetris.step() == StepResult::LineRemoved {
+                self.attack(attacker);
+            }
+        }
+        let knocked: Vec<usize> = (0..self.players.len())
+            .filter(|i| {
+                self.players[*i].placement.is_none() && self.players[*i].tetris.is_game_over()
+            })
+            .collect();
+        self.knock_out(&knocked);
+    }
+
+    // Actions are dropped before the battle starts and after player is knocked out
+    fn add_player_action(&mut self, player: usize, action: Action) {
+        if self.phase != MatchPhase::Playing {
+            return;
+        }
+        if let Some(battle_player) = self.players.get_mut(player) {
+            if battle_player.placement.is_none() {
+                battle_player.tetris.add_action(action);
+            }
+        }
+    }
+
+    // Player left the battle, the board is knocked out
+    fn forfeit(&mut self, player: usize) {
+        if self
+            .players
+            .get(player)
+            .is_some_and(|p| p.placement.is_none())
+        {
+            self.knock_out(&[player]);
+        }
+    }
+
+    // Knocked out player leaves the battle
+    fn is_done(&self, player: usize) -> bool {
+        self.players
+            .get(player)
+            .is_none_or(|p| p.placement.is_some())
+    }
+
+    fn get_tetris(&self, player: usize) -> Option<&Tetris> {
+        self.players.get(player).map(|p| &p.tetris)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Battle with fixed boards and targeting
+    fn seeded_battle(players: usize) -> Battle {
+        let rules = RuleSet::classic();
+        let mut battle = Battle::new(&rules, players);
+        for (i, player) in battle.players.iter_mut().enumerate() {
+            player.tetris = Tetris::with_seed(&rules, i as u64);
+        }
+        battle.rng = ChaCha12Rng::seed_from_u64(1);
+        battle
+    }
+
+    #[test]
+    fn knocked_out_players_share_placement() {
+        let mut battle = seeded_battle(4);
+        battle.players[3].last_attacker = Some(1);
+        battle.knock_out(&[3]);
+        assert_eq!(battle.players[3].placement, Some(4));
+        assert_eq!(battle.players[1].kos, 1);
+
+        // Attacker knocked out in the same step isn't credited
+        battle.players[1].last_attacker = Some(0);
+        battle.players[2].last_attacker = Some(1);
+        battle.knock_out(&[1, 2]);
+        assert_eq!(battle.players[1].placement, Some(2));
+        assert_eq!(battle.players[2].placement, Some(2));
+        assert!(battle.get_standing(1).unwrap().tied);
+        assert_eq!(battle.players[0].kos, 1);
+        assert_eq!(battle.players[1].kos, 1);
+
+        assert_eq!(battle.players[0].placement, Some(1));
+        assert!(!battle.get_standing(0).unwrap().tied);
+        assert!(battle.is_finished());
+        assert_eq!(battle.get_ranking(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn last_players_knocked_out_together_draw() {
+        let mut battle = seeded_battle(3);
+        battle.forfeit(0);
+        battle.knock_out(&[1, 2]);
+        assert_eq!(battle.players[0].placement, Some(3));
+        assert_eq!(battle.players[1].placement, Some(1));
+        assert_eq!(battle.players[2].placement, Some(1));
+        assert!(battle.get_standing(2).unwrap().tied);
+        assert!(battle.is_finished());
+    }
+
+    #[test]
+    fn targets_follow_targeting() {
+        let mut battle = seeded_battle(4);
+        battle.knock_out(&[2]);
+        // Random target is never the attacker or a knocked out player
+        for _ in 0..20 {
+            let targets = battle.choose_targets(0);
+            assert_eq!(targets.len(), 1);
+            assert!(targets[0] == 1 || targets[0] == 3);
+        }
+
+        battle.set_targeting(0, Targeting::Attackers);
+        battle.players[1].target = Some(0);
+        battle.players[3].target = Some(0);
+        assert_eq!(battle.choose_targets(0), [1, 3]);
+
+        for _ in 0..3 {
+            battle.players[3].tetris.bottom_refill();
+        }
+        assert!(stack_height(&battle.players[3].tetris) > 0);
+        battle.set_targeting(0, Targeting::Kos);
+        assert_eq!(battle.choose_targets(0), [3]);
+
+        battle.set_targeting(1, Targeting::Badges);
+        battle.players[0].kos = 2;
+        battle.players[3].kos = 1;
+        assert_eq!(battle.choose_targets(1), [0]);
+    }
+}
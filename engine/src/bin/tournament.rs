@@ -0,0 +1,168 @@
+// Headless tournament: plays bot-vs-bot matches without the server as fast as possible
+// and prints win rates and statistics. Matches use fixed seeds, so runs are reproducible.
+//
+// Usage: tournament [--matches N] [--seed S] [--max-steps N] [--rules RULES]
+//                   [--bot-a DIFFICULTY] [--bot-b DIFFICULTY]
+// where RULES is classic, guideline or chaos and DIFFICULTY is easy, medium or hard
+
+use std::time::Instant;
+
+use tetris_engine::{Bot, BotDifficulty, PlayerSide, RuleSet, TetrisPair};
+
+// Duration of single game step in milliseconds
+const STEP_MS: usize = 10;
+
+struct Settings {
+    matches: u64,
+    seed: u64,
+    // Match is a draw if nobody lost after this number of steps
+    max_steps: usize,
+    rules: RuleSet,
+    bot_a: BotDifficulty,
+    bot_b: BotDifficulty,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            matches: 100,
+            seed: 0,
+            max_steps: 10 * 60 * 1000 / STEP_MS,
+            rules: RuleSet::default(),
+            bot_a: BotDifficulty::MEDIUM,
+            bot_b: BotDifficulty::MEDIUM,
+        }
+    }
+}
+
+impl Settings {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut settings = Settings::default();
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {}", arg))?;
+            let invalid = || format!("Invalid value for {}: {}", arg, value);
+            match arg.as_str() {
+                "--matches" => settings.matches = value.parse().map_err(|_| invalid())?,
+                "--seed" => settings.seed = value.parse().map_err(|_| invalid())?,
+                "--max-steps" => settings.max_steps = value.parse().map_err(|_| invalid())?,
+                "--rules" => settings.rules = value.parse().map_err(|_| invalid())?,
+                "--bot-a" => settings.bot_a = value.parse().map_err(|_| invalid())?,
+                "--bot-b" => settings.bot_b = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Win(PlayerSide),
+    Draw,
+}
+
+struct MatchResult {
+    outcome: Outcome,
+    steps: usize,
+    // Lines and score of player A and player B
+    lines: [usize; 2],
+    score: [usize; 2],
+}
+
+fn play_match(settings: &Settings, seed: u64) -> MatchResult {
+    let mut pair = TetrisPair::with_seed(&settings.rules, seed);
+    pair.start();
+    // Bots get their own seeds, so equal bots don't make the same mistakes
+    let mut bots = [
+        (
+            PlayerSide::A,
+            Bot::with_seed(settings.bot_a, seed.wrapping_add(1)),
+        ),
+        (
+            PlayerSide::B,
+            Bot::with_seed(settings.bot_b, seed.wrapping_add(2)),
+        ),
+    ];
+    let mut steps = 0;
+    while steps < settings.max_steps && !pair.is_game_over() {
+        for (side, bot) in bots.iter_mut() {
+            let (tetris, _) = pair.get_player_tetris(*side);
+            if let Some(action) = bot.think(tetris) {
+                pair.add_player_action(*side, action);
+            }
+            pair.step_player(*side);
+        }
+        steps += 1;
+    }
+    let (tetris_a, tetris_b) = pair.get_player_tetris(PlayerSide::A);
+    let outcome = match (tetris_a.is_game_over(), tetris_b.is_game_over()) {
+        (true, false) => Outcome::Win(PlayerSide::B),
+        (false, true) => Outcome::Win(PlayerSide::A),
+        _ => Outcome::Draw,
+    };
+    MatchResult {
+        outcome,
+        steps,
+        lines: [tetris_a.get_lines(), tetris_b.get_lines()],
+        score: [tetris_a.get_score(), tetris_b.get_score()],
+    }
+}
+
+fn main() {
+    let settings = match Settings::from_args(std::env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "Playing {} matches, seed {}, {} rules: {:?} vs {:?}",
+        settings.matches, settings.seed, settings.rules.name, settings.bot_a, settings.bot_b
+    );
+
+    let started = Instant::now();
+    let results: Vec<MatchResult> = (0..settings.matches)
+        .map(|i| play_match(&settings, settings.seed.wrapping_add(i * 3)))
+        .collect();
+    let elapsed = started.elapsed();
+
+    let count = results.len().max(1) as f64;
+    let outcomes = |outcome: Outcome| results.iter().filter(|r| r.outcome == outcome).count();
+    let wins_a = outcomes(Outcome::Win(PlayerSide::A));
+    let wins_b = outcomes(Outcome::Win(PlayerSide::B));
+    let draws = outcomes(Outcome::Draw);
+    let total_steps: usize = results.iter().map(|r| r.steps).sum();
+    let average =
+        |f: &dyn Fn(&MatchResult) -> usize| results.iter().map(f).sum::<usize>() as f64 / count;
+
+    println!(
+        "Wins A: {} ({:.1}%), wins B: {} ({:.1}%), draws: {} ({:.1}%)",
+        wins_a,
+        wins_a as f64 * 100.0 / count,
+        wins_b,
+        wins_b as f64 * 100.0 / count,
+        draws,
+        draws as f64 * 100.0 / count
+    );
+    println!(
+        "Average match: {:.0} steps ({:.1} s of game time)",
+        average(&|r| r.steps),
+        average(&|r| r.steps * STEP_MS) / 1000.0
+    );
+    println!(
+        "Average lines: A {:.1}, B {:.1}; average score: A {:.0}, B {:.0}",
+        average(&|r| r.lines[0]),
+        average(&|r| r.lines[1]),
+        average(&|r| r.score[0]),
+        average(&|r| r.score[1])
+    );
+    println!(
+        "Simulated {} steps in {:.2} s ({:.0} steps/s)",
+        total_steps,
+        elapsed.as_secs_f64(),
+        total_steps as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+}
@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::tetris::{Action, CellType, Rotation, Tetris, Tetromino};
+
+// Weights of the board features, found by genetic optimization:
+// https://codemyroad.wordpress.com/2013/04/14/tetris-ai-the-near-perfect-player/
+const AGGREGATE_HEIGHT_WEIGHT: f64 = -0.510066;
+const COMPLETE_LINES_WEIGHT: f64 = 0.760666;
+const HOLES_WEIGHT: f64 = -0.35663;
+const BUMPINESS_WEIGHT: f64 = -0.184483;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BotDifficulty {
+    // Game steps between bot's actions
+    pub think_steps: usize,
+    // Probability to choose random placement instead of the best one
+    pub mistake_chance: f64,
+}
+
+impl BotDifficulty {
+    pub const EASY: BotDifficulty = BotDifficulty {
+        think_steps: 30,
+        mistake_chance: 0.3,
+    };
+    pub const MEDIUM: BotDifficulty = BotDifficulty {
+        think_steps: 15,
+        mistake_chance: 0.1,
+    };
+    pub const HARD: BotDifficulty = BotDifficulty {
+        think_steps: 5,
+        mistake_chance: 0.0,
+    };
+}
+
+impl std::str::FromStr for BotDifficulty {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(BotDifficulty::EASY),
+            "medium" => Ok(BotDifficulty::MEDIUM),
+            "hard" => Ok(BotDifficulty::HARD),
+            _ => Err(()),
+        }
+    }
+}
+
+// Final position of the tetromino: rotation and column of it's left side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Placement {
+    pub rotation: Rotation,
+    pub x: isize,
+}
+
+impl Placement {
+    // Actions moving tetromino from it's current position to the placement and dropping it
+    pub fn actions(&self, current: &Tetromino) -> VecDeque<Action> {
+        let mut actions = VecDeque::new();
+        let right_rotations = Rotation::ALL
+            .iter()
+            .position(|r| current.get_rotation() + *r == self.rotation)
+            .unwrap_or(0);
+        if right_rotations == 3 {
+            actions.push_back(Action::RotateLeft);
+        } else {
+            actions.extend((0..right_rotations).map(|_| Action::RotateRight));
+        }
+        let dx = self.x - current.get_x();
+        let shift = if dx < 0 {
+            Action::MoveLeft
+        } else {
+            Action::MoveRight
+        };
+        actions.extend((0..dx.abs()).map(|_| shift));
+        actions.push_back(Action::Drop);
+        actions
+    }
+}
+
+// Placements reachable from current position: tetromino is rotated in place,
+// moved horizontally and dropped straight down.
+// Returns each placement with the field after the drop
+pub fn legal_placements(
+    field: &Vec<Vec<CellType>>,
+    current: &Tetromino,
+) -> Vec<(Placement, Vec<Vec<CellType>>)> {
+    let mut placements = Vec::new();
+    for rotation in Rotation::ALL {
+        let rotated = Tetromino::new(
+            current.get_type(),
+            rotation,
+            current.get_x(),
+            current.get_y(),
+        );
+        if rotated.intersects(field, &[]) {
+            continue;
+        }
+        for dir in [-1, 1] {
+            let mut x = current.get_x();
+            // Field borders stop the shift too
+            loop {
+                let shifted = Tetromino::new(current.get_type(), rotation, x, current.get_y());
+                if shifted.intersects(field, &[]) {
+                    break;
+                }
+                // Current column is visited in both directions, take it once
+                if dir == -1 || x != current.get_x() {
+                    placements.push((Placement { rotation, x }, drop(field, shifted)));
+                }
+                x += dir;
+            }
+        }
+    }
+    placements
+}
+
+// Drop tetromino and get resulting field
+fn drop(field: &Vec<Vec<CellType>>, tetromino: Tetromino) -> Vec<Vec<CellType>> {
+    let mut landed = tetromino;
+    loop {
+        let next = Tetromino::new(
+            landed.get_type(),
+            landed.get_rotation(),
+            landed.get_x(),
+            landed.get_y() + 1,
+        );
+        if next.intersects(field, &[]) {
+            break;
+        }
+        landed = next;
+    }
+    let mut field = field.clone();
+    landed.draw(&mut field);
+    field
+}
+
+// Evaluate field after placement, higher is better
+pub fn evaluate(field: &[Vec<CellType>]) -> f64 {
+    let is_full = |row: &Vec<CellType>| row.iter().all(|cell| *cell != CellType::Empty);
+    let complete_lines = field.iter().filter(|row| is_full(row)).count();
+    // Features are calculated on the field with complete lines removed
+    let rest: Vec<&Vec<CellType>> = field.iter().filter(|row| !is_full(row)).collect();
+    let cols = field.first().map_or(0, |row| row.len());
+    let mut heights = vec![0; cols];
+    let mut holes = 0;
+    for (x, height) in heights.iter_mut().enumerate() {
+        let top = rest.iter().position(|row| row[x] != CellType::Empty);
+        if let Some(top) = top {
+            *height = rest.len() - top;
+            holes += rest[top..]
+                .iter()
+                .filter(|row| row[x] == CellType::Empty)
+                .count();
+        }
+    }
+    let aggregate_height: usize = heights.iter().sum();
+    let bumpiness: usize = heights.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+    AGGREGATE_HEIGHT_WEIGHT * aggregate_height as f64
+        + COMPLETE_LINES_WEIGHT * complete_lines as f64
+        + HOLES_WEIGHT * holes as f64
+        + BUMPINESS_WEIGHT * bumpiness as f64
+}
+
+// Choose the best placement for current tetromino
+pub fn best_placement(field: &Vec<Vec<CellType>>, current: &Tetromino) -> Option<Placement> {
+    legal_placements(field, current)
+        .into_iter()
+        .map(|(placement, field)| (placement, evaluate(&field)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(placement, _)| placement)
+}
+
+//
+// Computer player. Chooses placement for each new tetromino and sends actions
+// to reach it, one action per `think_steps` game steps
+//
+#[derive(Serialize, Deserialize)]
+pub struct Bot {
+    difficulty: BotDifficulty,
+    steps: usize,
+    // Actions for current tetromino, None if it's not planned yet
+    plan: Option<VecDeque<Action>>,
+    // Source of mistakes
+    rng: ChaCha12Rng,
+}
+
+impl Bot {
+    pub fn new(difficulty: BotDifficulty) -> Self {
+        Self::with_rng(difficulty, ChaCha12Rng::from_entropy())
+    }
+
+    // Create bot making the same mistakes on the same game
+    pub fn with_seed(difficulty: BotDifficulty, seed: u64) -> Self {
+        Self::with_rng(difficulty, ChaCha12Rng::seed_from_u64(seed))
+    }
+
+    fn with_rng(difficulty: BotDifficulty, rng: ChaCha12Rng) -> Self {
+        Bot {
+            difficulty,
+            steps: 0,
+            plan: None,
+            rng,
+        }
+    }
+
+    // Called on each game step, returns action to add to bot's board
+    pub fn think(&mut self, tetris: &Tetris) -> Option<Action> {
+        let Some(current) = tetris.get_current() else {
+            // Tetromino is fixed, plan next one when it appears
+            self.plan = None;
+            return None;
+        };
+        let plan = self.plan.get_or_insert_with(|| {
+            let placement = if self.rng.gen::<f64>() < self.difficulty.mistake_chance {
+                let mut placements = legal_placements(tetris.get_field(), current);
+                let count = placements.len();
+                (count > 0).then(|| placements.swap_remove(self.rng.gen_range(0..count)).0)
+            } else {
+                best_placement(tetris.get_field(), current)
+            };
+            placement.map_or_else(VecDeque::new, |placement| placement.actions(current))
+        });
+        self.steps += 1;
+        if self.steps < self.difficulty.think_steps {
+            return None;
+        }
+        self.steps = 0;
+        plan.pop_front()
+    }
+}
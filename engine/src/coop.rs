@@ -0,0 +1,487 @@
+use std::collections::VecDeque;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+use crate::{
+    event_regulator::EventRegulator,
+    group_matches::GroupField,
+    lifecycle::{MatchPhase, START_COUNTDOWN_STEPS},
+    rules::RuleSet,
+    tetris::{
+        queue_gravity, Action, CellType, Rotation, StepResult, Tetris, Tetromino, TetrominoType,
+    },
+};
+
+// Players sharing one field
+pub const COOP_PLAYERS: usize = 2;
+
+// Falling piece of one player with it's own actions queue and speeds
+struct CoopPiece {
+    current: Option<Tetromino>,
+    next: TetrominoType,
+    preview: Vec<Vec<CellType>>,
+    actions: VecDeque<Action>,
+    drop: bool,
+    game_speed: EventRegulator,
+    drop_speed: EventRegulator,
+    // Steps before the next piece is placed after the last one cleared lines
+    spawn_delay: usize,
+    // Player left the game, no more pieces are placed
+    left: bool,
+}
+
+//
+// Field shared by several players, each one controls it's own falling piece.
+// Field is as wide as fields of all players together, pieces of each player appear
+// above player's part of it. Falling pieces collide with each other as with fixed cells.
+// While blasted lines are shown and removed pieces stop, ones above removed line
+// are shifted down together with the field
+//
+pub struct CoopTetris {
+    // Shared field with lines, score and level. Fixing pieces, blasting and removing lines
+    // work as on any board, the board's own falling tetromino is never placed
+    board: Tetris,
+    // Columns above which pieces of each player appear
+    lane: usize,
+    pieces: Vec<CoopPiece>,
+}
+
+impl CoopTetris {
+    pub fn new(rules: &RuleSet, players: usize) -> Self {
+        let players = players.max(1);
+        let lane = rules.board_size().0;
+        let mut board = Tetris::with_cols(rules, lane * players, ChaCha12Rng::from_entropy());
+        let pieces = (0..players)
+            .map(|_| {
+                let size = rules.pieces.preview_size();
+                let mut preview = vec![vec![CellType::Empty; size]; size];
+                CoopPiece {
+                    current: None,
+                    next: board.next_tetromino_type(&mut preview),
+                    preview,
+                    actions: VecDeque::new(),
+                    drop: false,
+                    game_speed: EventRegulator::new(rules.gravity.events, rules.gravity.steps),
+                    drop_speed: EventRegulator::new(
+                        rules.drop_speed.events,
+                        rules.drop_speed.steps,
+                    ),
+                    spawn_delay: 0,
+                    left: false,
+                }
+            })
+            .collect();
+        CoopTetris {
+            board,
+            lane,
+            pieces,
+        }
+    }
+
+    pub fn add_action(&mut self, player: usize, action: Action) {
+        if let Some(piece) = self.pieces.get_mut(player) {
+            piece.actions.push_back(action);
+        }
+    }
+
+    // Step all pieces, same as Tetris::step for each of them
+    pub fn step(&mut self) -> StepResult {
+        if self.board.is_game_over() {
+            return StepResult::GameOver;
+        }
+        if self.board.wait_line_remove_delay() {
+            return StepResult::None;
+        }
+        if self.board.has_blasted_lines() {
+            for _ in 0..self.board.line_remove_steps() {
+                if let Some(line) = self.board.remove_top_blasted_line() {
+                    self.shift_pieces_above(line);
+                    return StepResult::LineRemoved;
+                }
+            }
+            return StepResult::None;
+        }
+        for player in 0..self.pieces.len() {
+            self.step_piece(player);
+            if self.board.is_game_over() {
+                return StepResult::GameOver;
+            }
+        }
+        StepResult::None
+    }
+
+    fn step_piece(&mut self, player: usize) {
+        let piece = &mut self.pieces[player];
+        if piece.left {
+            return;
+        }
+        if piece.current.is_none() {
+            if piece.spawn_delay > 0 {
+                piece.spawn_delay -= 1;
+                return;
+            }
+            if !self.place_next_tetromino(player) {
+                self.board.set_game_over();
+                return;
+            }
+        }
+        let piece = &mut self.pieces[player];
+        if piece.current.is_none() {
+            return;
+        }
+        let speed = if piece.drop {
+            &mut piece.drop_speed
+        } else {
+            &mut piece.game_speed
+        };
+        queue_gravity(&mut piece.actions, speed);
+        let Some(action) = piece.actions.pop_front() else {
+            return;
+        };
+        let succeed = match action {
+            Action::MoveLeft => self.change_piece(player, -1, 0, Rotation::R0),
+            Action::MoveRight => self.change_piece(player, 1, 0, Rotation::R0),
+            Action::MoveDown => self.change_piece(player, 0, 1, Rotation::R0),
+            Action::RotateLeft => self.change_piece(player, 0, 0, Rotation::R270),
+            Action::RotateRight => self.change_piece(player, 0, 0, Rotation::R90),
+            Action::Drop => {
+                self.pieces[player].drop = true;
+                true
+            }
+        };
+        // Piece which can't move down is fixed only when it lands on the field,
+        // on another falling piece it waits for that one to move away
+        if !succeed && action == Action::MoveDown && self.has_landed(player) {
+            self.fix_piece(player);
+        }
+    }
+
+    // Player's piece can't move down because of fixed cells or the field bottom
+    fn has_landed(&self, player: usize) -> bool {
+        self.pieces[player].current.is_some_and(|current| {
+            Tetromino::new(
+                current.get_type(),
+                current.get_rotation(),
+                current.get_x(),
+                current.get_y() + 1,
+            )
+            .intersects(self.board.get_field(), &[])
+        })
+    }
+
+    // Falling pieces of players other than `player`
+    fn other_pieces(&self, player: usize) -> Vec<Tetromino> {
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != player)
+            .filter_map(|(_, piece)| piece.current)
+            .collect()
+    }
+
+    // Place new tetromino above player's part of the field. Returns false if fixed cells
+    // don't leave place for it. Tetromino waits while another falling piece is in the way
+    fn place_next_tetromino(&mut self, player: usize) -> bool {
+        let new_tetromino = Tetromino::spawn(
+            self.pieces[player].next,
+            self.lane * player,
+            self.lane,
+            self.board.get_hidden_rows(),
+        );
+        if new_tetromino.intersects(self.board.get_field(), &[]) {
+            return false;
+        }
+        if new_tetromino.intersects(self.board.get_field(), &self.other_pieces(player)) {
+            return true;
+        }
+        let piece = &mut self.pieces[player];
+        piece.next = self.board.next_tetromino_type(&mut piece.preview);
+        piece.current = Some(new_tetromino);
+        piece.drop = false;
+        true
+    }
+
+    // Change position and rotation of player's piece, if it's possible
+    fn change_piece(&mut self, player: usize, x: isize, y: isize, rotation: Rotation) -> bool {
+        let Some(current) = self.pieces[player].current else {
+            return false;
+        };
+        let new_tetromino = Tetromino::new(
+            current.get_type(),
+            current.get_rotation() + rotation,
+            current.get_x() + x,
+            current.get_y() + y,
+        );
+        if new_tetromino.intersects(self.board.get_field(), &self.other_pieces(player)) {
+            return false;
+        }
+        self.pieces[player].current = Some(new_tetromino);
+        true
+    }
+
+    // Fix player's piece on the field. Lines it fills are removed after the delay,
+    // the player's next piece waits for them
+    fn fix_piece(&mut self, player: usize) {
+        let piece = &mut self.pieces[player];
+        piece.actions.clear();
+        let Some(current) = piece.current.take() else {
+            return;
+        };
+        if self
+            .board
+            .lock_piece(current)
+            .is_some_and(|lines| lines > 0)
+        {
+            self.pieces[player].spawn_delay = self.board.get_rules().line_remove_delay;
+            self.board.delay_line_remove();
+        }
+    }
+
+    // Falling pieces above the removed line are shifted down with the field.
+    // Blasted line is full, so falling pieces are either above or below it
+    fn shift_pieces_above(&mut self, line: usize) {
+        for piece in self.pieces.iter_mut() {
+            if let Some(current) = piece.current.filter(|c| c.get_y() < line as isize) {
+                piece.current = Some(Tetromino::new(
+                    current.get_type(),
+                    current.get_rotation(),
+                    current.get_x(),
+                    current.get_y() + 1,
+                ));
+            }
+        }
+    }
+
+    // Player left the game, player's piece is removed from the field
+    pub fn remove_player(&mut self, player: usize) {
+        if let Some(piece) = self.pieces.get_mut(player) {
+            piece.current = None;
+            piece.left = true;
+        }
+    }
+
+    pub fn has_left(&self, player: usize) -> bool {
+        self.pieces.get(player).is_none_or(|p| p.left)
+    }
+
+    pub fn players(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn get_field(&self) -> &Vec<Vec<CellType>> {
+        self.board.get_field()
+    }
+
+    // Rows of the field without hidden ones
+    pub fn get_visible_field(&self) -> &[Vec<CellType>] {
+        self.board.get_visible_field()
+    }
+
+    pub fn get_hidden_rows(&self) -> usize {
+        self.board.get_hidden_rows()
+    }
+
+    pub fn get_piece(&self, player: usize) -> Option<Tetromino> {
+        self.pieces.get(player).and_then(|p| p.current)
+    }
+
+    // Falling pieces of all players
+    pub fn get_pieces(&self) -> Vec<Tetromino> {
+        self.other_pieces(usize::MAX)
+    }
+
+    pub fn get_preview(&self, player: usize) -> Option<&Vec<Vec<CellType>>> {
+        self.pieces.get(player).map(|p| &p.preview)
+    }
+
+    pub fn get_score(&self) -> usize {
+        self.board.get_score()
+    }
+
+    pub fn get_lines(&self) -> usize {
+        self.board.get_lines()
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.board.is_game_over()
+    }
+}
+
+//
+// Co-op game of players sharing one field, stepped in lockstep like the other
+// matches of many players. The game is over when the field is topped out
+// or all players left it
+//
+pub struct CoopMatch {
+    tetris: CoopTetris,
+    stepped: Vec<bool>,
+    phase: MatchPhase,
+}
+
+impl CoopMatch {
+    pub fn new(rules: &RuleSet, players: usize) -> CoopMatch {
+        let tetris = CoopTetris::new(rules, players);
+        CoopMatch {
+            stepped: vec![false; tetris.players()],
+            tetris,
+            // Co-op games have no ready check, countdown starts at once
+            phase: MatchPhase::Countdown(START_COUNTDOWN_STEPS),
+        }
+    }
+
+    pub fn get_coop_tetris(&self) -> &CoopTetris {
+        &self.tetris
+    }
+
+    pub fn get_phase(&self) -> MatchPhase {
+        self.phase
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.phase == MatchPhase::Finished
+    }
+}
+
+impl GroupField for CoopMatch {
+    fn players(&self) -> usize {
+        self.tetris.players()
+    }
+
+    fn step_player(&mut self, player: usize) {
+        if let Some(stepped) = self.stepped.get_mut(player) {
+            *stepped = true;
+        }
+        let waiting = (0..self.stepped.len()).any(|i| !self.tetris.has_left(i) && !self.stepped[i]);
+        if waiting {
+            return;
+        }
+        self.stepped.fill(false);
+        if !self.phase.step_countdown() {
+            return;
+        }
+        if self.tetris.step() == StepResult::GameOver {
+            self.phase = MatchPhase::Finished;
+        }
+    }
+
+    // Actions are dropped before the game starts
+    fn add_player_action(&mut self, player: usize, action: Action) {
+        if self.phase == MatchPhase::Playing {
+            self.tetris.add_action(player, action);
+        }
+    }
+
+    // Partner keeps playing alone after the player left
+    fn forfeit(&mut self, player: usize) {
+        self.tetris.remove_player(player);
+        if (0..self.players()).all(|i| self.tetris.has_left(i)) {
+            self.phase = MatchPhase::Finished;
+        }
+    }
+
+    fn is_done(&self, _player: usize) -> bool {
+        self.is_finished()
+    }
+
+    // Players share one field which is not a Tetris board, see get_coop_tetris
+    fn get_tetris(&self, _player: usize) -> Option<&Tetris> {
+        None
+    }
+
+    // Score and lines are shared by all players
+    fn get_score(&self, _player: usize) -> usize {
+        self.tetris.get_score()
+    }
+
+    fn get_lines(&self, _player: usize) -> usize {
+        self.tetris.get_lines()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Co-op match which is already playing, with fixed sequence of pieces
+    fn playing_match() -> CoopMatch {
+        let rules = RuleSet::classic();
+        let mut coop = CoopMatch::new(&rules, COOP_PLAYERS);
+        let cols = coop.tetris.lane * COOP_PLAYERS;
+        coop.tetris.board = Tetris::with_cols(&rules, cols, ChaCha12Rng::seed_from_u64(1));
+        coop.phase = MatchPhase::Playing;
+        coop
+    }
+
+    fn step_all(coop: &mut CoopMatch) {
+        for player in 0..coop.players() {
+            coop.step_player(player);
+        }
+    }
+
+    #[test]
+    fn falling_pieces_collide() {
+        let mut coop = playing_match();
+        step_all(&mut coop);
+        let piece = coop.tetris.get_piece(0).unwrap();
+        assert!(piece.get_x() < coop.tetris.lane as isize);
+        assert!(coop.tetris.get_piece(1).unwrap().get_x() >= coop.tetris.lane as isize);
+
+        // Piece right below blocks the move, but the piece doesn't land on it
+        let below = Tetromino::new(
+            piece.get_type(),
+            piece.get_rotation(),
+            piece.get_x(),
+            piece.get_y() + 1,
+        );
+        coop.tetris.pieces[1].current = Some(below);
+        coop.add_player_action(0, Action::MoveDown);
+        coop.tetris.step_piece(0);
+        assert_eq!(coop.tetris.get_piece(0), Some(piece));
+        assert!(!coop.tetris.has_landed(0));
+
+        coop.tetris.remove_player(1);
+        assert!(coop.tetris.change_piece(0, 0, 1, Rotation::R0));
+    }
+
+    #[test]
+    fn piece_waits_for_falling_piece_in_the_way() {
+        let mut coop = playing_match();
+        let next = coop.tetris.pieces[0].next;
+        let hidden_rows = coop.tetris.get_hidden_rows();
+        let spawn = Tetromino::spawn(next, 0, coop.tetris.lane, hidden_rows);
+        coop.tetris.pieces[1].current = Some(spawn);
+        assert!(coop.tetris.place_next_tetromino(0));
+        assert_eq!(coop.tetris.get_piece(0), None);
+
+        coop.tetris.pieces[1].current = None;
+        assert!(coop.tetris.place_next_tetromino(0));
+        assert_eq!(coop.tetris.get_piece(0), Some(spawn));
+    }
+
+    #[test]
+    fn game_is_over_when_fixed_cells_block_new_piece() {
+        let mut coop = playing_match();
+        let next = coop.tetris.pieces[0].next;
+        let hidden_rows = coop.tetris.get_hidden_rows();
+        let spawn = Tetromino::spawn(next, 0, coop.tetris.lane, hidden_rows);
+        coop.tetris.board.lock_piece(spawn);
+        assert!(!coop.tetris.place_next_tetromino(0));
+        step_all(&mut coop);
+        assert!(coop.tetris.is_game_over());
+        assert!(coop.is_finished());
+    }
+
+    #[test]
+    fn game_is_finished_when_all_players_left() {
+        let mut coop = playing_match();
+        coop.forfeit(0);
+        assert!(!coop.is_finished());
+        // Partner plays alone, the game isn't waiting for the player who left
+        coop.step_player(1);
+        assert!(coop.tetris.get_piece(1).is_some());
+        assert_eq!(coop.tetris.get_piece(0), None);
+        coop.forfeit(1);
+        assert!(coop.is_finished());
+    }
+}
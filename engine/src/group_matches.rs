@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::matches::{MatchId, PlayerStatus};
+use crate::tetris::{Action, Tetris};
+
+//
+// Matchmaking of matches with any number of players. Players wait in the lobby
+// in order of joining, the caller decides when to start a match from the lobby.
+// Players are identified in the field by their index in the match
+//
+pub struct GroupMatch<K, V> {
+    pub players: Vec<K>,
+    pub field: V,
+}
+
+impl<K: Eq, V> GroupMatch<K, V> {
+    pub fn new(players: Vec<K>, field: V) -> GroupMatch<K, V> {
+        GroupMatch { players, field }
+    }
+    pub fn get_player_index(&self, player: &K) -> Option<usize> {
+        self.players.iter().position(|p| p == player)
+    }
+}
+
+// Field of a match with any number of players, players are identified by their index
+pub trait GroupField {
+    fn players(&self) -> usize;
+    // Boards are stepped when all players still in the game have called step
+    fn step_player(&mut self, player: usize);
+    fn add_player_action(&mut self, player: usize, action: Action);
+    // Player left the match, the board is out of the game
+    fn forfeit(&mut self, player: usize);
+    // Player's part of the match is over, the player can leave it
+    fn is_done(&self, player: usize) -> bool;
+    fn get_tetris(&self, player: usize) -> Option<&Tetris>;
+    // Player's score and lines, taken from the player's board
+    fn get_score(&self, player: usize) -> usize {
+        self.get_tetris(player)
+            .map_or(0, |tetris| tetris.get_score())
+    }
+    fn get_lines(&self, player: usize) -> usize {
+        self.get_tetris(player)
+            .map_or(0, |tetris| tetris.get_lines())
+    }
+    // Order of boards shown to the player, player's own board goes first
+    fn board_order(&self, player: usize) -> Vec<usize> {
+        std::iter::once(player)
+            .chain((0..self.players()).filter(|i| *i != player))
+            .collect()
+    }
+}
+
+pub struct GroupMatches<K, V>
+where
+    K: Copy + Eq + Hash,
+{
+    lobby: Vec<K>,
+    match_ids: HashMap<K, MatchId>,
+    matches: HashMap<MatchId, GroupMatch<K, V>>,
+    next_match_id: MatchId,
+}
+
+impl<K, V> Default for GroupMatches<K, V>
+where
+    K: Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> GroupMatches<K, V>
+where
+    K: Copy + Eq + Hash,
+{
+    pub fn new() -> GroupMatches<K, V> {
+        GroupMatches {
+            lobby: Vec::new(),
+            match_ids: HashMap::new(),
+            matches: HashMap::new(),
+            next_match_id: 0,
+        }
+    }
+
+    pub fn get_player_status(&self, player: &K) -> PlayerStatus {
+        if self.match_ids.contains_key(player) {
+            PlayerStatus::Match
+        } else if self.lobby.contains(player) {
+            PlayerStatus::WaitList
+        } else {
+            PlayerStatus::NotFound
+        }
+    }
+
+    // Add player to the lobby unless already waiting or playing
+    pub fn join(&mut self, player: K) {
+        if self.get_player_status(&player) == PlayerStatus::NotFound {
+            self.lobby.push(player);
+        }
+    }
+    pub fn leave_lobby(&mut self, player: &K) {
+        self.lobby.retain(|p| p != player);
+    }
+    pub fn lobby_len(&self) -> usize {
+        self.lobby.len()
+    }
+
+    // Start match with up to `max_players` players who joined first.
+    // Field is created for the number of players in the match
+    pub fn start_match(
+        &mut self,
+        max_players: usize,
+        new_field: impl FnOnce(usize) -> V,
+    ) -> Option<MatchId> {
+        if self.lobby.is_empty() {
+            return None;
+        }
+        let count = max_players.min(self.lobby.len());
+        let players: Vec<K> = self.lobby.drain(..count).collect();
+        // Ids are never reused, so removed matches can't be confused with new ones
+        let match_id = self.next_match_id;
+        self.next_match_id += 1;
+        for player in &players {
+            self.match_ids.insert(*player, match_id);
+        }
+        let field = new_field(players.len());
+        self.matches
+            .insert(match_id, GroupMatch::new(players, field));
+        Some(match_id)
+    }
+
+    // Forget player's match, the board stays in the field.
+    // The match is removed and returned when the last player left it
+    pub fn leave_match(&mut self, player: &K) -> Option<GroupMatch<K, V>> {
+        let match_id = self.match_ids.remove(player)?;
+        let group_match = self.matches.get(&match_id)?;
+        if group_match
+            .players
+            .iter()
+            .any(|p| self.match_ids.get(p) == Some(&match_id))
+        {
+            return None;
+        }
+        self.matches.remove(&match_id)
+    }
+    // Returns removed match if it existed
+    pub fn remove_match(&mut self, match_id: MatchId) -> Option<GroupMatch<K, V>> {
+        let group_match = self.matches.remove(&match_id)?;
+        for player in &group_match.players {
+            if self.match_ids.get(player) == Some(&match_id) {
+                self.match_ids.remove(player);
+            }
+        }
+        Some(group_match)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (MatchId, &GroupMatch<K, V>)> {
+        self.matches.iter().map(|(match_id, m)| (*match_id, m))
+    }
+    pub fn get_match(&self, match_id: &MatchId) -> Option<&GroupMatch<K, V>> {
+        self.matches.get(match_id)
+    }
+    pub fn get_mut_match(&mut self, match_id: &MatchId) -> Option<&mut GroupMatch<K, V>> {
+        self.matches.get_mut(match_id)
+    }
+    pub fn get_match_for_player(&self, player: &K) -> Option<(MatchId, &GroupMatch<K, V>)> {
+        let match_id = self.match_ids.get(player)?;
+        self.matches.get(match_id).map(|m| (*match_id, m))
+    }
+    pub fn get_mut_match_for_player(
+        &mut self,
+        player: &K,
+    ) -> Option<(MatchId, &mut GroupMatch<K, V>)> {
+        let match_id = self.match_ids.get(player)?;
+        self.matches.get_mut(match_id).map(|m| (*match_id, m))
+    }
+}
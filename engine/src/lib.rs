@@ -0,0 +1,31 @@
+//
+// Tetris duel game engine: boards, pairs of boards stepped in lockstep, battles
+// and team matches of many boards, co-op games on a shared board, matchmaking
+// of players into matches and the computer player
+//
+pub mod battle;
+pub mod bot;
+pub mod coop;
+pub mod event_regulator;
+pub mod group_matches;
+pub mod lifecycle;
+pub mod matches;
+pub mod pause;
+pub mod pieces;
+pub mod rules;
+pub mod teams;
+pub mod tetris;
+pub mod tetris_pair;
+
+pub use battle::{Battle, BattleStanding, Targeting};
+pub use bot::{Bot, BotDifficulty, Placement};
+pub use coop::{CoopMatch, CoopTetris};
+pub use group_matches::{GroupField, GroupMatch, GroupMatches};
+pub use lifecycle::{Lifecycle, MatchPhase};
+pub use matches::{Match, MatchId, Matches, PlayerSide, PlayerStatus, WaitList};
+pub use pause::{Pause, PauseState};
+pub use pieces::{PieceMask, PieceSet, PieceShape};
+pub use rules::{GarbageRule, RuleSet, Speed};
+pub use teams::TeamMatch;
+pub use tetris::{Action, CellType, Rotation, StepResult, Tetris, Tetromino, TetrominoType};
+pub use tetris_pair::{TetrisPair, TetrisPairState};
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+use crate::matches::PlayerSide;
+
+// Game steps between the moment both players are ready and the start of the match
+pub const START_COUNTDOWN_STEPS: usize = 300;
+// Game steps given to both players to confirm they are ready
+pub const READY_CHECK_STEPS: usize = 1800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MatchPhase {
+    // Match is created, waiting for both players to connect
+    #[default]
+    Matched,
+    // Both players are connected, waiting for both of them to confirm they are ready
+    ReadyCheck,
+    // Match is about to start, number of steps left
+    Countdown(usize),
+    Playing,
+    // One of the boards is topped out
+    Finished,
+}
+
+impl MatchPhase {
+    // Count down and start the match. Returns true if the boards should be stepped
+    pub fn step_countdown(&mut self) -> bool {
+        match *self {
+            MatchPhase::Countdown(0) => {
+                *self = MatchPhase::Playing;
+                true
+            }
+            MatchPhase::Countdown(steps) => {
+                *self = MatchPhase::Countdown(steps - 1);
+                false
+            }
+            MatchPhase::Playing => true,
+            _ => false,
+        }
+    }
+}
+
+//
+// Lifecycle of a match from matchmaking to the end of the game. Boards are stepped
+// only while the match is playing. Players can confirm they are ready as soon as
+// the match is created, the ready check is passed when both did it. The ready check
+// expires if it's not passed in READY_CHECK_STEPS
+//
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lifecycle {
+    phase: MatchPhase,
+    ready_a: bool,
+    ready_b: bool,
+    #[serde(default)]
+    ready_check_steps: usize,
+    // Player has seen the finished match
+    #[serde(default)]
+    seen_finished_a: bool,
+    #[serde(default)]
+    seen_finished_b: bool,
+}
+
+impl Lifecycle {
+    // Mark player as ready, ignored after the ready check is passed
+    pub fn set_ready(&mut self, player: PlayerSide) {
+        if matches!(self.phase, MatchPhase::Matched | MatchPhase::ReadyCheck) {
+            match player {
+                PlayerSide::A => self.ready_a = true,
+                PlayerSide::B => self.ready_b = true,
+            }
+        }
+    }
+
+    // Called on each game step, i.e. when both players are connected.
+    // Returns true if the boards should be stepped
+    pub fn step(&mut self) -> bool {
+        match self.phase {
+            MatchPhase::Matched => {
+                self.phase = MatchPhase::ReadyCheck;
+                false
+            }
+            MatchPhase::ReadyCheck => {
+                if self.ready_a && self.ready_b {
+                    self.phase = MatchPhase::Countdown(START_COUNTDOWN_STEPS);
+                } else {
+                    self.ready_check_steps += 1;
+                }
+                false
+            }
+            _ => self.phase.step_countdown(),
+        }
+    }
+
+    // Skip the ready check and countdown
+    pub fn start(&mut self) {
+        self.phase = MatchPhase::Playing;
+    }
+
+    pub fn finish(&mut self) {
+        self.phase = MatchPhase::Finished;
+    }
+
+    // Mark that player has seen the finished match.
+    // Returns true when both players have seen it, so the match can be removed
+    pub fn see_finished(&mut self, player: PlayerSide) -> bool {
+        if self.phase != MatchPhase::Finished {
+            return false;
+        }
+        match player {
+            PlayerSide::A => self.seen_finished_a = true,
+            PlayerSide::B => self.seen_finished_b = true,
+        }
+        self.seen_finished_a && self.seen_finished_b
+    }
+
+    // Players didn't confirm they are ready in time
+    pub fn is_ready_check_expired(&self) -> bool {
+        self.phase == MatchPhase::ReadyCheck && self.ready_check_steps >= READY_CHECK_STEPS
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.phase == MatchPhase::Playing
+    }
+
+    pub fn is_ready(&self, player: PlayerSide) -> bool {
+        match player {
+            PlayerSide::A => self.ready_a,
+            PlayerSide::B => self.ready_b,
+        }
+    }
+
+    pub fn get_phase(&self) -> MatchPhase {
+        self.phase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready_check_expires() {
+        let mut lifecycle = Lifecycle::default();
+        lifecycle.set_ready(PlayerSide::A);
+        for _ in 0..=READY_CHECK_STEPS {
+            assert!(!lifecycle.is_ready_check_expired());
+            lifecycle.step();
+        }
+        assert!(lifecycle.is_ready_check_expired());
+        lifecycle.set_ready(PlayerSide::B);
+        lifecycle.step();
+        assert!(!lifecycle.is_ready_check_expired());
+        assert_eq!(
+            lifecycle.get_phase(),
+            MatchPhase::Countdown(START_COUNTDOWN_STEPS)
+        );
+    }
+
+    #[test]
+    fn finished_match_is_seen_by_both_players() {
+        let mut lifecycle = Lifecycle::default();
+        lifecycle.start();
+        assert!(!lifecycle.see_finished(PlayerSide::A));
+        lifecycle.finish();
+        assert!(!lifecycle.see_finished(PlayerSide::B));
+        assert!(!lifecycle.see_finished(PlayerSide::B));
+        assert!(lifecycle.see_finished(PlayerSide::A));
+    }
+}
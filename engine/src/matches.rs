@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+//
+// Stores pairs of players and play fields for each pair
+//
+pub struct Match<K: Eq, V> {
+    pub player_a: K,
+    pub player_b: K,
+    pub field: V,
+    // Number of spectators watching the match
+    pub spectators: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
+pub enum PlayerSide {
+    A,
+    B,
+}
+
+impl PlayerSide {
+    pub fn opponent(&self) -> PlayerSide {
+        match self {
+            PlayerSide::A => PlayerSide::B,
+            PlayerSide::B => PlayerSide::A,
+        }
+    }
+}
+
+impl<K: Eq, V> Match<K, V> {
+    pub fn new(player_a: K, player_b: K, field: V) -> Match<K, V> {
+        Match {
+            player_a,
+            player_b,
+            field,
+            spectators: 0,
+        }
+    }
+    pub fn get_player(&self, side: PlayerSide) -> &K {
+        match side {
+            PlayerSide::A => &self.player_a,
+            PlayerSide::B => &self.player_b,
+        }
+    }
+    pub fn get_player_side(&self, player: &K) -> Option<PlayerSide> {
+        if player == &self.player_a {
+            Some(PlayerSide::A)
+        } else if player == &self.player_b {
+            Some(PlayerSide::B)
+        } else {
+            None
+        }
+    }
+}
+
+pub trait WaitList<K> {
+    fn add(&mut self, player: K);
+    fn remove(&mut self, player: &K);
+    fn exists(&self, player: &K) -> bool;
+    // Find waiting player other than `player` for whom `compatible` returns true
+    fn find_matching_pair(&self, player: &K, compatible: &dyn Fn(&K) -> bool) -> Option<&K>;
+}
+
+impl<K: PartialEq + Eq + Hash> WaitList<K> for HashSet<K> {
+    fn add(&mut self, player: K) {
+        self.insert(player);
+    }
+    fn remove(&mut self, player: &K) {
+        self.remove(player);
+    }
+    fn exists(&self, player: &K) -> bool {
+        self.contains(player)
+    }
+    fn find_matching_pair(&self, player: &K, compatible: &dyn Fn(&K) -> bool) -> Option<&K> {
+        // Get first compatible key from wait list not equal to player
+        self.iter().find(|k| k != &player && compatible(k))
+    }
+}
+
+pub type MatchId = usize;
+
+pub struct Matches<K, V, WL = HashSet<K>>
+where
+    K: Copy + Eq + Hash,
+    WL: WaitList<K> + Default,
+    V: Default,
+{
+    wait_list: WL,
+    match_ids: HashMap<K, MatchId>,
+    matches: HashMap<MatchId, Match<K, V>>,
+    // Players removed by moderator, their sessions should be closed
+    kicked: HashSet<K>,
+    next_match_id: MatchId,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PlayerStatus {
+    Match,
+    WaitList,
+    NotFound,
+}
+
+impl<K, WL, V> Default for Matches<K, V, WL>
+where
+    K: Copy + Eq + Hash,
+    WL: WaitList<K> + Default,
+    V: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, WL, V> Matches<K, V, WL>
+where
+    K: Copy + Eq + Hash,
+    WL: WaitList<K> + Default,
+    V: Default,
+{
+    pub fn new() -> Matches<K, V, WL> {
+        Matches {
+            wait_list: WL::default(),
+            match_ids: HashMap::new(),
+            matches: HashMap::new(),
+            kicked: HashSet::new(),
+            next_match_id: 0,
+        }
+    }
+
+    pub fn get_player_status(&self, player: &K) -> PlayerStatus {
+        if self.match_ids.contains_key(player) {
+            PlayerStatus::Match
+        } else if self.wait_list.exists(player) {
+            PlayerStatus::WaitList
+        } else {
+            PlayerStatus::NotFound
+        }
+    }
+
+    pub fn find_match(&mut self, player: &K) -> bool {
+        self.find_match_with(player, |_| true, V::default)
+    }
+    // Find match only with compatible players, field of the new match is created with `new_field`
+    pub fn find_match_with(
+        &mut self,
+        player: &K,
+        compatible: impl Fn(&K) -> bool,
+        new_field: impl FnOnce() -> V,
+    ) -> bool {
+        // Check if player is already in match
+        if let Some(_) = self.match_ids.get(player) {
+            true
+        } else if let Some(player_b) = self.wait_list.find_matching_pair(player, &compatible) {
+            // Matching player found, create a new match
+            let player_b = *player_b;
+            self.create_match_with(*player, player_b, new_field());
+            true
+        } else {
+            // Matching player not found, add to wait list
+            self.wait_list.add(*player);
+            false
+        }
+    }
+    // Create match for two players, removing them from the wait list
+    pub fn create_match(&mut self, player_a: K, player_b: K) -> MatchId {
+        self.create_match_with(player_a, player_b, V::default())
+    }
+    // Same as `create_match` with field of the match given
+    pub fn create_match_with(&mut self, player_a: K, player_b: K, field: V) -> MatchId {
+        // Ids are never reused, so removed matches can't be confused with new ones
+        let match_id = self.next_match_id;
+        self.next_match_id += 1;
+        self.wait_list.remove(&player_a);
+        self.wait_list.remove(&player_b);
+        self.matches
+            .insert(match_id, Match::new(player_a, player_b, field));
+        self.match_ids.insert(player_a, match_id);
+        self.match_ids.insert(player_b, match_id);
+        match_id
+    }
+    // Remove player who gave up waiting for opponent
+    pub fn leave_wait_list(&mut self, player: &K) {
+        self.wait_list.remove(player);
+    }
+    // Returns removed match if it existed
+    pub fn remove_match(&mut self, match_id: MatchId) -> Option<Match<K, V>> {
+        let match_ = self.matches.remove(&match_id)?;
+        self.match_ids.remove(&match_.player_a);
+        self.match_ids.remove(&match_.player_b);
+        Some(match_)
+    }
+    // Remove player from wait list and from the match if any.
+    // Player is marked as kicked until `take_kicked` is called.
+    // Returns false if player was not found
+    pub fn kick(&mut self, player: &K) -> bool {
+        let found = if let Some(match_id) = self.match_ids.get(player) {
+            let match_id = *match_id;
+            self.remove_match(match_id);
+            true
+        } else if self.wait_list.exists(player) {
+            self.wait_list.remove(player);
+            true
+        } else {
+            false
+        };
+        if found {
+            self.kicked.insert(*player);
+        }
+        found
+    }
+    // Check if player was kicked and clear kicked mark
+    pub fn take_kicked(&mut self, player: &K) -> bool {
+        self.kicked.remove(player)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (MatchId, &Match<K, V>)> {
+        self.matches.iter().map(|(match_id, m)| (*match_id, m))
+    }
+    pub fn get_match(&self, match_id: &MatchId) -> Option<&Match<K, V>> {
+        self.matches.get(match_id)
+    }
+    pub fn get_mut_match(&mut self, match_id: &MatchId) -> Option<&mut Match<K, V>> {
+        self.matches.get_mut(match_id)
+    }
+    pub fn get_match_for_player(&self, player: &K) -> Option<(MatchId, &Match<K, V>)> {
+        if let Some(match_id) = self.match_ids.get(player) {
+            self.matches.get(match_id).map(|m| (*match_id, m))
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut_match_for_player(&mut self, player: &K) -> Option<(MatchId, &mut Match<K, V>)> {
+        if let Some(match_id) = self.match_ids.get(player) {
+            self.matches.get_mut(match_id).map(|m| (*match_id, m))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+use crate::matches::PlayerSide;
+
+// Game steps between resume request and the moment boards start stepping again
+pub const RESUME_COUNTDOWN_STEPS: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PauseState {
+    #[default]
+    Running,
+    // Pause requested by the player, game continues until opponent agrees
+    Requested(PlayerSide),
+    Paused,
+    // Game is about to continue, number of steps left
+    Countdown(usize),
+}
+
+//
+// Pause of a match or single player game. In a match both players have to request
+// the pause, single player game is paused at once. Number of pauses is limited
+//
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Pause {
+    state: PauseState,
+    pauses_left: usize,
+}
+
+impl Pause {
+    pub fn new(pauses: usize) -> Self {
+        Pause {
+            state: PauseState::Running,
+            pauses_left: pauses,
+        }
+    }
+
+    // Request pause by one of the players, the game is paused when both did it.
+    // Returns true if the game is paused
+    pub fn request(&mut self, player: PlayerSide) -> bool {
+        match self.state {
+            PauseState::Running if self.pauses_left > 0 => {
+                self.state = PauseState::Requested(player)
+            }
+            PauseState::Requested(requested_by) if requested_by != player => self.pause(),
+            _ => {}
+        }
+        self.state == PauseState::Paused
+    }
+
+    // Pause without consent of other player, used in single player game
+    pub fn request_now(&mut self) -> bool {
+        if matches!(self.state, PauseState::Running) && self.pauses_left > 0 {
+            self.pause();
+        }
+        self.state == PauseState::Paused
+    }
+
+    fn pause(&mut self) {
+        self.pauses_left -= 1;
+        self.state = PauseState::Paused;
+    }
+
+    // Start countdown of paused game or withdraw pause request
+    pub fn resume(&mut self) {
+        match self.state {
+            PauseState::Paused => self.state = PauseState::Countdown(RESUME_COUNTDOWN_STEPS),
+            PauseState::Requested(_) => self.state = PauseState::Running,
+            _ => {}
+        }
+    }
+
+    // Called on each game step, returns true if the game should be stepped
+    pub fn step(&mut self) -> bool {
+        match self.state {
+            PauseState::Running | PauseState::Requested(_) => true,
+            PauseState::Paused => false,
+            PauseState::Countdown(0) => {
+                self.state = PauseState::Running;
+                true
+            }
+            PauseState::Countdown(steps) => {
+                self.state = PauseState::Countdown(steps - 1);
+                false
+            }
+        }
+    }
+
+    // Game is not stepped, including countdown before resuming
+    pub fn is_paused(&self) -> bool {
+        matches!(self.state, PauseState::Paused | PauseState::Countdown(_))
+    }
+
+    pub fn get_state(&self) -> PauseState {
+        self.state
+    }
+
+    pub fn get_pauses_left(&self) -> usize {
+        self.pauses_left
+    }
+}
@@ -0,0 +1,284 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::tetris::{CellType, Rotation, TetrominoType};
+
+// Biggest width and height of a piece
+pub const MAX_PIECE_SIZE: usize = 8;
+
+//
+// Cells of a piece in one rotation. Bit `y * MAX_PIECE_SIZE + x` of `cells` is set
+// for filled cell, so a cell check is a bit test and filled cells are listed
+// without scanning the bounding box
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PieceMask {
+    cells: u64,
+    width: u8,
+    height: u8,
+}
+
+impl PieceMask {
+    pub(crate) const fn new(cells: u64, width: usize, height: usize) -> Self {
+        PieceMask {
+            cells,
+            width: width as u8,
+            height: height as u8,
+        }
+    }
+
+    // Mask of the same piece turned by `rotation`
+    pub const fn rotate(&self, rotation: Rotation) -> Self {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (rotated_width, rotated_height) = rotation.rotate_size(width, height);
+        let mut cells = 0;
+        let mut y = 0;
+        while y < rotated_height {
+            let mut x = 0;
+            while x < rotated_width {
+                let (source_x, source_y) = rotation.source_cell(x, y, width, height);
+                if self.cells & 1 << (source_y * MAX_PIECE_SIZE + source_x) != 0 {
+                    cells |= 1 << (y * MAX_PIECE_SIZE + x);
+                }
+                x += 1;
+            }
+            y += 1;
+        }
+        PieceMask::new(cells, rotated_width, rotated_height)
+    }
+
+    // Masks of all rotations in order of Rotation::ALL
+    pub const fn rotations(&self) -> [Self; 4] {
+        [
+            *self,
+            self.rotate(Rotation::R90),
+            self.rotate(Rotation::R180),
+            self.rotate(Rotation::R270),
+        ]
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width as usize
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height as usize
+    }
+
+    pub fn get_cell(&self, x: usize, y: usize) -> bool {
+        x < self.get_width()
+            && y < self.get_height()
+            && self.cells & 1 << (y * MAX_PIECE_SIZE + x) != 0
+    }
+
+    // Positions (x, y) of filled cells, row by row
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let mut cells = self.cells;
+        std::iter::from_fn(move || {
+            if cells == 0 {
+                return None;
+            }
+            let bit = cells.trailing_zeros() as usize;
+            cells &= cells - 1;
+            Some((bit % MAX_PIECE_SIZE, bit / MAX_PIECE_SIZE))
+        })
+    }
+}
+
+//
+// Shape of a custom piece, e.g. pentomino. Masks of all rotations are computed once
+// when the shape is parsed. Shapes are small and copied with tetrominoes, so custom
+// pieces need no registry of shapes
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PieceShape {
+    masks: [PieceMask; 4],
+    cell_type: CellType,
+}
+
+impl PieceShape {
+    // Parse shape drawn by rows, '#' or 'X' for filled cells, '.' or ' ' for empty ones.
+    // Empty rows and columns around the shape are trimmed
+    pub fn from_rows<S: AsRef<str>>(rows: &[S], cell_type: CellType) -> Result<Self, String> {
+        let mut filled = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.as_ref().chars().enumerate() {
+                match c {
+                    '#' | 'X' => filled.push((x, y)),
+                    '.' | ' ' => {}
+                    _ => return Err(format!("invalid character '{}' in piece shape", c)),
+                }
+            }
+        }
+        let (Some(left), Some(top)) = (
+            filled.iter().map(|(x, _)| *x).min(),
+            filled.iter().map(|(_, y)| *y).min(),
+        ) else {
+            return Err("piece shape has no cells".to_string());
+        };
+        let width = filled.iter().map(|(x, _)| x - left + 1).max().unwrap_or(0);
+        let height = filled.iter().map(|(_, y)| y - top + 1).max().unwrap_or(0);
+        if width > MAX_PIECE_SIZE || height > MAX_PIECE_SIZE {
+            return Err(format!(
+                "piece shape is {}x{}, allowed up to {}x{}",
+                width, height, MAX_PIECE_SIZE, MAX_PIECE_SIZE
+            ));
+        }
+        let cells = filled.iter().fold(0, |cells, (x, y)| {
+            cells | 1 << ((y - top) * MAX_PIECE_SIZE + x - left)
+        });
+        Ok(PieceShape {
+            masks: PieceMask::new(cells, width, height).rotations(),
+            cell_type,
+        })
+    }
+
+    pub fn get_masks(&self) -> &[PieceMask; 4] {
+        &self.masks
+    }
+
+    pub fn get_cell_type(&self) -> CellType {
+        self.cell_type
+    }
+}
+
+// Piece of a piece set file: shape drawn by rows and color given as one of tetrominoes
+#[derive(Debug, Deserialize)]
+pub struct PieceDef {
+    pub shape: Vec<String>,
+    pub color: String,
+}
+
+//
+// Piece set file, e.g.
+// {"name": "party", "pieces": [{"shape": ["#"], "color": "O"}, {"shape": [".#.", "###"], "color": "T"}]}
+//
+#[derive(Debug, Deserialize)]
+pub struct PieceSetDef {
+    pub name: String,
+    pub pieces: Vec<PieceDef>,
+}
+
+// Pieces falling in the game, each one has the same chance to be the next
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PieceSet {
+    pub name: String,
+    pieces: Vec<TetrominoType>,
+}
+
+impl PieceSet {
+    // Seven tetrominoes of the original game
+    pub fn tetrominoes() -> Self {
+        PieceSet {
+            name: "tetrominoes".to_string(),
+            pieces: vec![
+                TetrominoType::I,
+                TetrominoType::J,
+                TetrominoType::L,
+                TetrominoType::O,
+                TetrominoType::S,
+                TetrominoType::T,
+                TetrominoType::Z,
+            ],
+        }
+    }
+
+    // Twelve pentominoes, boards of at least 5 columns are needed
+    pub fn pentominoes() -> Self {
+        let shapes: [(&[&str], CellType); 12] = [
+            (&[".##", "##.", ".#."], CellType::T),
+            (&["#####"], CellType::I),
+            (&["#...", "####"], CellType::L),
+            (&["##..", ".###"], CellType::S),
+            (&["##", "##", "#."], CellType::O),
+            (&["###", ".#.", ".#."], CellType::T),
+            (&["#.#", "###"], CellType::J),
+            (&["#..", "#..", "###"], CellType::L),
+            (&["#..", "##.", ".##"], CellType::Z),
+            (&[".#.", "###", ".#."], CellType::O),
+            (&[".#..", "####"], CellType::J),
+            (&["##.", ".#.", ".##"], CellType::S),
+        ];
+        PieceSet {
+            name: "pentominoes".to_string(),
+            pieces: shapes
+                .iter()
+                .map(|(rows, cell_type)| {
+                    TetrominoType::Custom(PieceShape::from_rows(rows, *cell_type).unwrap())
+                })
+                .collect(),
+        }
+    }
+
+    // Piece set of a file, fails on invalid shape or color
+    pub fn from_def(def: PieceSetDef) -> Result<Self, String> {
+        if def.pieces.is_empty() {
+            return Err(format!("piece set {} has no pieces", def.name));
+        }
+        let pieces = def
+            .pieces
+            .iter()
+            .map(|piece| {
+                let cell_type = piece
+                    .color
+                    .parse()
+                    .map_err(|_| format!("unknown piece color: {}", piece.color))?;
+                PieceShape::from_rows(&piece.shape, cell_type).map(TetrominoType::Custom)
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|e| format!("piece set {}: {}", def.name, e))?;
+        Ok(PieceSet {
+            name: def.name,
+            pieces,
+        })
+    }
+
+    // Random piece, tetrominoes are chosen the same way as by TetrominoType::new_random
+    pub fn random(&self, rng: &mut impl Rng) -> TetrominoType {
+        if self.pieces.is_empty() {
+            return TetrominoType::new_random(rng);
+        }
+        self.pieces[rng.gen::<u32>() as usize % self.pieces.len()]
+    }
+
+    pub fn get_pieces(&self) -> &[TetrominoType] {
+        &self.pieces
+    }
+
+    // Longest side of the biggest piece
+    pub fn max_size(&self) -> usize {
+        self.pieces
+            .iter()
+            .map(|piece| {
+                piece
+                    .get_width(&Rotation::R0)
+                    .max(piece.get_height(&Rotation::R0))
+            })
+            .max()
+            .unwrap_or(4)
+    }
+
+    // Size of the preview field, big enough for any piece of the set
+    pub fn preview_size(&self) -> usize {
+        self.max_size().max(4)
+    }
+}
+
+impl Default for PieceSet {
+    fn default() -> Self {
+        PieceSet::tetrominoes()
+    }
+}
+
+// Get preset by name
+impl std::str::FromStr for PieceSet {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tetrominoes" => Ok(PieceSet::tetrominoes()),
+            "pentominoes" => Ok(PieceSet::pentominoes()),
+            _ => Err(()),
+        }
+    }
+}
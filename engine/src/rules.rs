@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pieces::PieceSet;
+
+// Limits of the board size. Board must fit any tetromino, which is up to 4 cells long
+pub const MIN_COLS: usize = 4;
+pub const MAX_COLS: usize = 40;
+pub const MIN_ROWS: usize = 4;
+pub const MAX_ROWS: usize = 40;
+pub const MAX_HIDDEN_ROWS: usize = 40;
+
+//
+// Rules of the game: board size, speeds and garbage sent to the opponent.
+// Speeds are given as number of events per number of game steps, see EventRegulator
+//
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleSet {
+    pub name: String,
+    pub cols: usize,
+    // Visible rows of the board
+    pub rows: usize,
+    // Buffer rows above the visible area where new tetrominoes appear.
+    // Garbage can push the stack there without ending the game
+    #[serde(default)]
+    pub hidden_rows: usize,
+    // Rows fallen by tetromino
+    pub gravity: Speed,
+    // Rows fallen by dropped tetromino
+    pub drop_speed: Speed,
+    // Blasted lines removed
+    pub blast_speed: Speed,
+    // Steps to show blasted lines before they are removed
+    pub line_remove_delay: usize,
+    pub garbage: GarbageRule,
+    // Pauses allowed per match, 0 for competitive play
+    pub pauses: usize,
+    // Falling pieces, the seven tetrominoes by default
+    #[serde(default)]
+    pub pieces: PieceSet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Speed {
+    pub events: usize,
+    pub steps: usize,
+}
+
+impl Speed {
+    pub const fn new(events: usize, steps: usize) -> Self {
+        Speed { events, steps }
+    }
+}
+
+// Garbage rows pushed from the bottom of opponent's field when player removes lines
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GarbageRule {
+    // Rows sent for each removed line, 0 to play without garbage
+    pub rows_per_line: usize,
+    // Probability of each garbage cell to be filled
+    pub fill_chance: f32,
+}
+
+impl RuleSet {
+    // Original rules of the game
+    pub fn classic() -> Self {
+        RuleSet {
+            name: "classic".to_string(),
+            cols: 10,
+            rows: 20,
+            hidden_rows: 0,
+            gravity: Speed::new(1, 100),
+            drop_speed: Speed::new(1, 10),
+            blast_speed: Speed::new(3, 10),
+            line_remove_delay: 10,
+            garbage: GarbageRule {
+                rows_per_line: 1,
+                fill_chance: 0.5,
+            },
+            pauses: 3,
+            pieces: PieceSet::tetrominoes(),
+        }
+    }
+
+    // Closer to modern competitive games: 10x40 board with 20 visible rows, instant drop,
+    // garbage rows with few holes and no pauses
+    pub fn guideline() -> Self {
+        RuleSet {
+            name: "guideline".to_string(),
+            cols: 10,
+            rows: 20,
+            hidden_rows: 20,
+            gravity: Speed::new(1, 100),
+            drop_speed: Speed::new(1, 1),
+            blast_speed: Speed::new(1, 1),
+            line_remove_delay: 40,
+            garbage: GarbageRule {
+                rows_per_line: 1,
+                fill_chance: 0.9,
+            },
+            pauses: 0,
+            pieces: PieceSet::tetrominoes(),
+        }
+    }
+
+    // Bigger field, fast gravity and lots of garbage
+    pub fn chaos() -> Self {
+        RuleSet {
+            name: "chaos".to_string(),
+            cols: 12,
+            rows: 24,
+            hidden_rows: 0,
+            gravity: Speed::new(1, 25),
+            drop_speed: Speed::new(1, 5),
+            blast_speed: Speed::new(5, 10),
+            line_remove_delay: 5,
+            garbage: GarbageRule {
+                rows_per_line: 2,
+                fill_chance: 0.6,
+            },
+            pauses: 3,
+            pieces: PieceSet::tetrominoes(),
+        }
+    }
+
+    // Check board size is within limits, returns description of the first problem
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_COLS..=MAX_COLS).contains(&self.cols) {
+            return Err(format!(
+                "board of {} rules has {} columns, allowed {} to {}",
+                self.name, self.cols, MIN_COLS, MAX_COLS
+            ));
+        }
+        if !(MIN_ROWS..=MAX_ROWS).contains(&self.rows) {
+            return Err(format!(
+                "board of {} rules has {} visible rows, allowed {} to {}",
+                self.name, self.rows, MIN_ROWS, MAX_ROWS
+            ));
+        }
+        if self.hidden_rows > MAX_HIDDEN_ROWS {
+            return Err(format!(
+                "board of {} rules has {} hidden rows, allowed up to {}",
+                self.name, self.hidden_rows, MAX_HIDDEN_ROWS
+            ));
+        }
+        // Pieces must fit the board in any rotation
+        let piece_size = self.pieces.max_size();
+        if piece_size > self.cols || piece_size > self.rows + self.hidden_rows {
+            return Err(format!(
+                "pieces of {} rules are up to {} cells long and don't fit the board",
+                self.name, piece_size
+            ));
+        }
+        if self.pieces.get_pieces().is_empty() {
+            return Err(format!("{} rules have no pieces", self.name));
+        }
+        Ok(())
+    }
+
+    // Board size within limits: columns, visible rows and hidden rows. Used by boards
+    // created with rules which were not validated
+    pub fn board_size(&self) -> (usize, usize, usize) {
+        (
+            self.cols.clamp(MIN_COLS, MAX_COLS),
+            self.rows.clamp(MIN_ROWS, MAX_ROWS),
+            self.hidden_rows.min(MAX_HIDDEN_ROWS),
+        )
+    }
+}
+
+impl RuleSet {
+    // All presets which can be chosen by name
+    pub fn presets() -> [RuleSet; 3] {
+        [RuleSet::classic(), RuleSet::guideline(), RuleSet::chaos()]
+    }
+}
+
+// Get preset by name
+impl std::str::FromStr for RuleSet {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(RuleSet::classic()),
+            "guideline" => Ok(RuleSet::guideline()),
+            "chaos" => Ok(RuleSet::chaos()),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet::classic()
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    group_matches::GroupField,
+    lifecycle::{MatchPhase, START_COUNTDOWN_STEPS},
+    rules::RuleSet,
+    tetris::{Action, StepResult, SystemAction, Tetris},
+};
+
+pub const TEAM_SIZE: usize = 2;
+pub const TEAMS: usize = 2;
+
+struct TeamPlayer {
+    tetris: Tetris,
+    stepped: bool,
+    // Board is topped out or player left the match
+    out: bool,
+}
+
+//
+// Match of two teams of two players. Lines removed by teammates are sent as garbage
+// to the boards of the opposing team in turn, so the team attacks as one.
+// Team is eliminated when all its boards are out, the other team wins.
+// Players 0 and 1 form the first team, players 2 and 3 the second one
+//
+pub struct TeamMatch {
+    players: Vec<TeamPlayer>,
+    phase: MatchPhase,
+    // Next board of each team to receive garbage
+    next_target: [usize; TEAMS],
+    // None for a draw when both teams are eliminated at once
+    winner: Option<usize>,
+}
+
+impl TeamMatch {
+    pub fn new(rules: &RuleSet) -> TeamMatch {
+        TeamMatch {
+            players: (0..TEAM_SIZE * TEAMS)
+                .map(|_| TeamPlayer {
+                    tetris: Tetris::new(rules),
+                    stepped: false,
+                    out: false,
+                })
+                .collect(),
+            // Team matches have no ready check, countdown starts at once
+            phase: MatchPhase::Countdown(START_COUNTDOWN_STEPS),
+            next_target: [0; TEAMS],
+            winner: None,
+        }
+    }
+
+    pub fn get_team(player: usize) -> usize {
+        player / TEAM_SIZE
+    }
+
+    // Players of the team in order of their indexes
+    pub fn team_players(team: usize) -> impl Iterator<Item = usize> {
+        team * TEAM_SIZE..(team + 1) * TEAM_SIZE
+    }
+
+    // Send garbage for one line removed by the team to the next board of the other team
+    fn attack(&mut self, team: usize) {
+        let target_team = (team + 1) % TEAMS;
+        let alive: Vec<usize> = Self::team_players(target_team)
+            .filter(|i| !self.players[*i].out)
+            .collect();
+        if alive.is_empty() {
+            return;
+        }
+        let target = alive[self.next_target[target_team] % alive.len()];
+        self.next_target[target_team] += 1;
+        let tetris = &mut self.players[target].tetris;
+        for _ in 0..tetris.get_rules().garbage.rows_per_line {
+            tetris.add_system_action(SystemAction::BottomRefill);
+        }
+    }
+
+    // Finish the match when a team has no boards left
+    fn check_teams(&mut self) {
+        let alive: Vec<bool> = (0..TEAMS)
+            .map(|team| Self::team_players(team).any(|i| !self.players[i].out))
+            .collect();
+        if alive.iter().filter(|a| **a).count() <= 1 {
+            self.winner = alive.iter().position(|a| *a);
+            self.phase = MatchPhase::Finished;
+        }
+    }
+
+    pub fn is_out(&self, player: usize) -> bool {
+        self.players.get(player).is_none_or(|p| p.out)
+    }
+
+    pub fn get_team_lines(&self, team: usize) -> usize {
+        Self::team_players(team)
+            .map(|i| self.players[i].tetris.get_lines())
+            .sum()
+    }
+
+    pub fn get_team_score(&self, team: usize) -> usize {
+        Self::team_players(team)
+            .map(|i| self.players[i].tetris.get_score())
+            .sum()
+    }
+
+    // Winning team, None while playing or for a draw
+    pub fn get_winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    pub fn get_phase(&self) -> MatchPhase {
+        self.phase
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.phase == MatchPhase::Finished
+    }
+}
+
+impl GroupField for TeamMatch {
+    fn players(&self) -> usize {
+        self.players.len()
+    }
+
+    fn step_player(&mut self, player: usize) {
+        if let Some(team_player) = self.players.get_mut(player) {
+            team_player.stepped = true;
+        }
+        if self.players.iter().any(|p| !p.out && !p.stepped) {
+            return;
+        }
+        for team_player in self.players.iter_mut() {
+            team_player.stepped = false;
+        }
+        if !self.phase.step_countdown() {
+            return;
+        }
+        for player in 0..self.players.len() {
+            if self.players[player].out {
+                continue;
+            }
+            if self.players[player].tetris.step() == StepResult::LineRemoved {
+                self.attack(Self::get_team(player));
+            }
+        }
+        for team_player in self.players.iter_mut() {
+            team_player.out |= team_player.tetris.is_game_over();
+        }
+        self.check_teams();
+    }
+
+    // Actions are dropped before the match starts and after player's board is out
+    fn add_player_action(&mut self, player: usize, action: Action) {
+        if self.phase != MatchPhase::Playing {
+            return;
+        }
+        if let Some(team_player) = self.players.get_mut(player) {
+            if !team_player.out {
+                team_player.tetris.add_action(action);
+            }
+        }
+    }
+
+    // Leaving the finished match doesn't change its result
+    fn forfeit(&mut self, player: usize) {
+        if self.is_finished() {
+            return;
+        }
+        if let Some(team_player) = self.players.get_mut(player) {
+            team_player.out = true;
+        }
+        self.check_teams();
+    }
+
+    // Player whose board is out keeps watching the teammate until the match is finished
+    fn is_done(&self, _player: usize) -> bool {
+        self.is_finished()
+    }
+
+    fn get_tetris(&self, player: usize) -> Option<&Tetris> {
+        self.players.get(player).map(|p| &p.tetris)
+    }
+
+    // Player's own board, then the teammate, then the opposing team
+    fn board_order(&self, player: usize) -> Vec<usize> {
+        let team = Self::get_team(player);
+        let teammates = Self::team_players(team).filter(|i| *i != player);
+        let opponents = (0..TEAMS)
+            .filter(|t| *t != team)
+            .flat_map(Self::team_players);
+        std::iter::once(player)
+            .chain(teammates)
+            .chain(opponents)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Team match which is already playing, so boards are stepped at once
+    fn playing_match() -> TeamMatch {
+        let rules = RuleSet::classic();
+        let mut team_match = TeamMatch::new(&rules);
+        for (i, player) in team_match.players.iter_mut().enumerate() {
+            player.tetris = Tetris::with_seed(&rules, i as u64);
+        }
+        team_match.phase = MatchPhase::Playing;
+        team_match
+    }
+
+    fn step_all(team_match: &mut TeamMatch) {
+        for player in 0..team_match.players() {
+            team_match.step_player(player);
+        }
+    }
+
+    #[test]
+    fn team_is_eliminated_when_all_boards_are_out() {
+        let mut team_match = playing_match();
+        team_match.players[2].tetris.set_game_over();
+        step_all(&mut team_match);
+        assert!(team_match.is_out(2));
+        assert!(!team_match.is_finished());
+
+        // Teammate of the board which is out keeps playing
+        team_match.forfeit(1);
+        step_all(&mut team_match);
+        assert!(!team_match.is_finished());
+
+        team_match.players[3].tetris.set_game_over();
+        step_all(&mut team_match);
+        assert!(team_match.is_finished());
+        assert_eq!(team_match.get_winner(), Some(0));
+
+        // Leaving the finished match doesn't change the winner
+        team_match.forfeit(0);
+        assert_eq!(team_match.get_winner(), Some(0));
+    }
+
+    #[test]
+    fn teams_eliminated_together_draw() {
+        let mut team_match = playing_match();
+        team_match.forfeit(0);
+        team_match.forfeit(3);
+        for player in [1, 2] {
+            team_match.players[player].tetris.set_game_over();
+        }
+        step_all(&mut team_match);
+        assert!(team_match.is_finished());
+        assert_eq!(team_match.get_winner(), None);
+    }
+}
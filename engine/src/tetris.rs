@@ -1,5 +1,9 @@
 use crate::event_regulator::EventRegulator;
-use rocket::serde::Serialize;
+use crate::pieces::{PieceMask, PieceSet, PieceShape, MAX_PIECE_SIZE};
+use crate::rules::RuleSet;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -25,9 +29,29 @@ impl serde::Serialize for CellType {
     }
 }
 
+impl<'de> serde::Deserialize<'de> for CellType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match u64::deserialize(deserializer)? {
+            0 => Ok(CellType::Empty),
+            1 => Ok(CellType::Blasted),
+            2 => Ok(CellType::I),
+            3 => Ok(CellType::J),
+            4 => Ok(CellType::L),
+            5 => Ok(CellType::O),
+            6 => Ok(CellType::S),
+            7 => Ok(CellType::T),
+            8 => Ok(CellType::Z),
+            n => Err(serde::de::Error::custom(format!("invalid cell type {}", n))),
+        }
+    }
+}
+
 impl CellType {
-    pub fn new_random() -> CellType {
-        match rand::random::<u8>() % 7 {
+    pub fn new_random(rng: &mut impl Rng) -> CellType {
+        match rng.gen::<u8>() % 7 {
             0 => CellType::I,
             1 => CellType::J,
             2 => CellType::L,
@@ -40,7 +64,25 @@ impl CellType {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+// Color of a piece by name of the tetromino having it
+impl std::str::FromStr for CellType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "I" => Ok(CellType::I),
+            "J" => Ok(CellType::J),
+            "L" => Ok(CellType::L),
+            "O" => Ok(CellType::O),
+            "S" => Ok(CellType::S),
+            "T" => Ok(CellType::T),
+            "Z" => Ok(CellType::Z),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Rotation {
     R0,
     R90,
@@ -49,6 +91,9 @@ pub enum Rotation {
 }
 
 impl Rotation {
+    // All rotations in clockwise order
+    pub const ALL: [Rotation; 4] = [Rotation::R0, Rotation::R90, Rotation::R180, Rotation::R270];
+
     // Rotate left
     pub fn rotate_left(&self) -> Rotation {
         match self {
@@ -58,6 +103,28 @@ impl Rotation {
             Rotation::R270 => Rotation::R180,
         }
     }
+    // Size of shape of `width` x `height` cells after rotation
+    pub const fn rotate_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Rotation::R0 | Rotation::R180 => (width, height),
+            Rotation::R90 | Rotation::R270 => (height, width),
+        }
+    }
+    // Cell (x, y) of the rotated shape of `width` x `height` cells in not rotated shape
+    pub const fn source_cell(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> (usize, usize) {
+        match self {
+            Rotation::R0 => (x, y),
+            Rotation::R90 => (y, height - x - 1),
+            Rotation::R180 => (width - x - 1, height - y - 1),
+            Rotation::R270 => (width - y - 1, x),
+        }
+    }
     // Rotate right
     pub fn rotate_right(&self) -> Rotation {
         match self {
@@ -90,35 +157,21 @@ struct TetrominoMatrix {
 }
 
 impl TetrominoMatrix {
-    // Get width of tetromino matrix considering rotation
-    pub fn get_width(&self, rotation: &Rotation) -> usize {
-        // Return width of tetromino matrix considering rotation
-        match rotation {
-            Rotation::R0 => self.width,
-            Rotation::R90 => self.height,
-            Rotation::R180 => self.width,
-            Rotation::R270 => self.height,
-        }
-    }
-    // Get height of tetromino matrix considering rotation
-    pub fn get_height(&self, rotation: &Rotation) -> usize {
-        // Return height of tetromino matrix considering rotation
-        match rotation {
-            Rotation::R0 => self.height,
-            Rotation::R90 => self.width,
-            Rotation::R180 => self.height,
-            Rotation::R270 => self.width,
-        }
-    }
-    // Get cell value of tetromino matrix considering rotation
-    pub fn get_cell(&self, x: usize, y: usize, rotation: &Rotation) -> bool {
-        // Return cell value of tetromino matrix considering rotation
-        match rotation {
-            Rotation::R0 => self.matrix[y][x],
-            Rotation::R90 => self.matrix[self.height - x - 1][y],
-            Rotation::R180 => self.matrix[self.height - y - 1][self.width - x - 1],
-            Rotation::R270 => self.matrix[x][self.width - y - 1],
+    // Masks of tetromino in all rotations
+    const fn masks(&self) -> [PieceMask; 4] {
+        let mut cells = 0;
+        let mut y = 0;
+        while y < self.height {
+            let mut x = 0;
+            while x < self.width {
+                if self.matrix[y][x] {
+                    cells |= 1 << (y * MAX_PIECE_SIZE + x);
+                }
+                x += 1;
+            }
+            y += 1;
         }
+        PieceMask::new(cells, self.width, self.height).rotations()
     }
 }
 
@@ -206,21 +259,17 @@ const TETROMINO_Z_R0: TetrominoMatrix = TetrominoMatrix {
     height: 2,
 };
 
-// Get tetromino matrix by tetromino type
-fn get_tetromino_matrix(tetromino_type: &TetrominoType) -> &TetrominoMatrix {
-    // Return tetromino matrix by tetromino type
-    match tetromino_type {
-        TetrominoType::I => &TETROMINO_I_R0,
-        TetrominoType::J => &TETROMINO_J_R0,
-        TetrominoType::L => &TETROMINO_L_R0,
-        TetrominoType::O => &TETROMINO_O_R0,
-        TetrominoType::S => &TETROMINO_S_R0,
-        TetrominoType::T => &TETROMINO_T_R0,
-        TetrominoType::Z => &TETROMINO_Z_R0,
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+// Masks of tetrominoes in all rotations, computed at compile time, so moves and
+// collision checks don't remap cells for every rotation
+const TETROMINO_I_MASKS: [PieceMask; 4] = TETROMINO_I_R0.masks();
+const TETROMINO_J_MASKS: [PieceMask; 4] = TETROMINO_J_R0.masks();
+const TETROMINO_L_MASKS: [PieceMask; 4] = TETROMINO_L_R0.masks();
+const TETROMINO_O_MASKS: [PieceMask; 4] = TETROMINO_O_R0.masks();
+const TETROMINO_S_MASKS: [PieceMask; 4] = TETROMINO_S_R0.masks();
+const TETROMINO_T_MASKS: [PieceMask; 4] = TETROMINO_T_R0.masks();
+const TETROMINO_Z_MASKS: [PieceMask; 4] = TETROMINO_Z_R0.masks();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TetrominoType {
     I,
     J,
@@ -229,14 +278,16 @@ pub enum TetrominoType {
     S,
     T,
     Z,
+    // Piece of a custom piece set, see PieceSet
+    Custom(PieceShape),
 }
 
 impl TetrominoType {
     // new method returns new tetromino type
-    pub fn new_random() -> Self {
+    pub fn new_random(rng: &mut impl Rng) -> Self {
         // Create new tetromino type
         // Create random number between 0 and 6
-        let random_number = rand::random::<u32>() % 7;
+        let random_number = rng.gen::<u32>() % 7;
         // Return new tetromino type
         match random_number {
             0 => TetrominoType::I,
@@ -250,22 +301,34 @@ impl TetrominoType {
         }
     }
 
+    // Get precomputed cells of tetromino in given rotation
+    pub fn get_mask(&self, rotation: &Rotation) -> PieceMask {
+        let masks = match self {
+            TetrominoType::I => &TETROMINO_I_MASKS,
+            TetrominoType::J => &TETROMINO_J_MASKS,
+            TetrominoType::L => &TETROMINO_L_MASKS,
+            TetrominoType::O => &TETROMINO_O_MASKS,
+            TetrominoType::S => &TETROMINO_S_MASKS,
+            TetrominoType::T => &TETROMINO_T_MASKS,
+            TetrominoType::Z => &TETROMINO_Z_MASKS,
+            TetrominoType::Custom(shape) => shape.get_masks(),
+        };
+        masks[*rotation as usize]
+    }
+
     // Get tetromino width depending on rotation
     pub fn get_width(&self, rotation: &Rotation) -> usize {
-        // Return tetromino width depending on rotation
-        get_tetromino_matrix(self).get_width(rotation)
+        self.get_mask(rotation).get_width()
     }
 
     // Get tetromino height depending on rotation
     pub fn get_height(&self, rotation: &Rotation) -> usize {
-        // Return tetromino height depending on rotation
-        get_tetromino_matrix(self).get_height(rotation)
+        self.get_mask(rotation).get_height()
     }
 
     // Get cell value depending on rotation
     pub fn get_cell(&self, x: usize, y: usize, rotation: &Rotation) -> bool {
-        // Return cell value depending on rotation
-        get_tetromino_matrix(self).get_cell(x, y, rotation)
+        self.get_mask(rotation).get_cell(x, y)
     }
 
     // Get cell type corresponding to tetromino type
@@ -279,11 +342,12 @@ impl TetrominoType {
             TetrominoType::S => CellType::S,
             TetrominoType::T => CellType::T,
             TetrominoType::Z => CellType::Z,
+            TetrominoType::Custom(shape) => shape.get_cell_type(),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Tetromino {
     // Tetromino type
     tetromino_type: TetrominoType,
@@ -306,8 +370,9 @@ impl Tetromino {
         }
     }
 
-    // Check if tetromino intersects with field borders or other tetrominos
-    pub fn intersects(&self, field: &Vec<Vec<CellType>>) -> bool {
+    // Check if tetromino intersects with field borders, fixed cells or other falling
+    // tetrominoes, e.g. pieces of other players on a shared field
+    pub fn intersects(&self, field: &Vec<Vec<CellType>>, others: &[Tetromino]) -> bool {
         // Check if tetromino intersects with field borders or other tetrominos
         // Check if tetromino position is positive, otherwise it intersects with field borders
         let x = if self.x >= 0 {
@@ -320,22 +385,25 @@ impl Tetromino {
         } else {
             return true;
         };
-        // Get tetromino width and height
-        let width = self.tetromino_type.get_width(&self.rotation);
-        let height = self.tetromino_type.get_height(&self.rotation);
+        let mask = self.tetromino_type.get_mask(&self.rotation);
         // Check if tetromino intersects with field borders
-        if x + width > field[0].len() || y + height > field.len() {
+        if x + mask.get_width() > field[0].len() || y + mask.get_height() > field.len() {
             return true;
         }
-        // Check if tetromino intersects with other tetrominos
-        for cell_y in 0..height {
-            for cell_x in 0..width {
-                if self.tetromino_type.get_cell(cell_x, cell_y, &self.rotation)
-                    && field[y + cell_y][x + cell_x] != CellType::Empty
-                {
-                    return true;
-                }
-            }
+        // Check if tetromino intersects with fixed cells
+        if mask
+            .cells()
+            .any(|(cell_x, cell_y)| field[y + cell_y][x + cell_x] != CellType::Empty)
+        {
+            return true;
+        }
+        // Check if tetromino intersects with other falling tetrominoes
+        if others.iter().any(|other| {
+            other
+                .cells()
+                .any(|cell| self.cells().any(|own| own == cell))
+        }) {
+            return true;
         }
         // Return false if tetromino does not intersect with field borders or other tetrominos
         false
@@ -344,30 +412,67 @@ impl Tetromino {
     // Draw tetromino on field. If tetromino intersects with field borders, draw it partially.
     // I.e for any cell position check is it inside field borders and if it is, draw it.
     pub fn draw(&self, field: &mut Vec<Vec<CellType>>) {
-        // Draw tetromino on field
-        // Get tetromino width and height
-        let width = self.tetromino_type.get_width(&self.rotation);
-        let height = self.tetromino_type.get_height(&self.rotation);
         let cell_type = self.tetromino_type.get_cell_type();
-        // Draw tetromino on field
-        for cell_y in 0..height {
-            for cell_x in 0..width {
-                if self.tetromino_type.get_cell(cell_x, cell_y, &self.rotation) {
-                    // Get cell position. Use isize type to avoid overflow
-                    // Check resulting positoins are positive and less than field borders
-                    let x = self.x + cell_x as isize;
-                    let y = self.y + cell_y as isize;
-                    if x >= 0 && x < field[0].len() as isize && y >= 0 && y < field.len() as isize {
-                        field[y as usize][x as usize] = cell_type;
-                    }
-                }
+        // Check cell positions are positive and less than field borders
+        for (x, y) in self.cells() {
+            if x >= 0 && x < field[0].len() as isize && y >= 0 && y < field.len() as isize {
+                field[y as usize][x as usize] = cell_type;
             }
         }
     }
+
+    // New tetromino centered horizontally over `cols` columns starting from `left`,
+    // placed in hidden rows right above the visible area or at the top if there are too few of them
+    pub fn spawn(
+        tetromino_type: TetrominoType,
+        left: usize,
+        cols: usize,
+        hidden_rows: usize,
+    ) -> Self {
+        let width = tetromino_type.get_width(&Rotation::R0);
+        let height = tetromino_type.get_height(&Rotation::R0);
+        let x = left + cols.saturating_sub(width) / 2;
+        let y = hidden_rows.saturating_sub(height);
+        Tetromino::new(tetromino_type, Rotation::R0, x as isize, y as isize)
+    }
+
+    // Get field positions (x, y) of all cells occupied by tetromino
+    pub fn get_cells(&self) -> Vec<(isize, isize)> {
+        self.cells().collect()
+    }
+
+    // Field positions of cells without allocation. Use isize type to avoid overflow
+    fn cells(&self) -> impl Iterator<Item = (isize, isize)> {
+        let (x, y) = (self.x, self.y);
+        self.tetromino_type
+            .get_mask(&self.rotation)
+            .cells()
+            .map(move |(cell_x, cell_y)| (x + cell_x as isize, y + cell_y as isize))
+    }
+
+    pub fn get_cell_type(&self) -> CellType {
+        self.tetromino_type.get_cell_type()
+    }
+
+    pub fn get_type(&self) -> TetrominoType {
+        self.tetromino_type
+    }
+
+    pub fn get_rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    pub fn get_x(&self) -> isize {
+        self.x
+    }
+
+    pub fn get_y(&self) -> isize {
+        self.y
+    }
 }
 
 // Enum with all possible user actions
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Action {
     MoveLeft,
     MoveRight,
@@ -375,6 +480,11 @@ pub enum Action {
     RotateLeft,
     RotateRight,
     Drop,
+}
+
+// Actions generated by the game itself, e.g. garbage sent by opponent. Can't be sent by players
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum SystemAction {
     BottomRefill,
 }
 
@@ -390,10 +500,25 @@ pub enum StepResult {
     GameOver,
 }
 
+// Queue moves down made by gravity or drop in this step
+pub(crate) fn queue_gravity(actions: &mut VecDeque<Action>, speed: &mut EventRegulator) {
+    for _ in 0..speed.step() {
+        actions.push_back(Action::MoveDown);
+    }
+}
+
+// Score for removing 0, 1, 2, 3 and 4 lines at once, multiplied by level
+const LINE_SCORES: [usize; 5] = [0, 100, 300, 500, 800];
+
+// Full state of the game, serialized snapshot continues exactly where it was taken
+#[derive(Serialize, Deserialize)]
 pub struct Tetris {
-    // Game field size
+    // Game field size, rows include hidden ones
     cols: usize,
     rows: usize,
+    // Rows above the visible area
+    #[serde(default)]
+    hidden_rows: usize,
     // Game over flag
     game_over: bool,
     // Game field
@@ -406,6 +531,8 @@ pub struct Tetris {
     next: TetrominoType,
     // User actions queue
     actions: VecDeque<Action>,
+    // System actions queue, not cleared when tetromino is fixed
+    system_actions: VecDeque<SystemAction>,
     // Drop state
     drop: bool,
     // Game speed
@@ -418,29 +545,51 @@ pub struct Tetris {
     line_remove_delay: Option<usize>,
     // Game score
     score: usize,
+    // Number of removed lines
+    lines: usize,
+    // Game level, affects gravity and score for lines
+    level: usize,
+    // Source of tetrominoes and garbage cells, seeded to make the game reproducible
+    rng: ChaCha12Rng,
+    rules: RuleSet,
 }
 
 impl Default for Tetris {
     fn default() -> Self {
-        Tetris::new(10, 20)
+        Tetris::new(&RuleSet::default())
     }
 }
 
 impl Tetris {
-    pub fn new(width: usize, height: usize) -> Self {
+    pub fn new(rules: &RuleSet) -> Self {
+        Self::with_rng(rules, ChaCha12Rng::from_entropy())
+    }
+
+    // Create game with fixed sequence of tetrominoes and garbage
+    pub fn with_seed(rules: &RuleSet, seed: u64) -> Self {
+        Self::with_rng(rules, ChaCha12Rng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rules: &RuleSet, rng: ChaCha12Rng) -> Self {
+        Self::with_cols(rules, rules.board_size().0, rng)
+    }
+
+    // Board of `width` columns instead of the rules' ones, e.g. field shared by several players
+    pub(crate) fn with_cols(rules: &RuleSet, width: usize, mut rng: ChaCha12Rng) -> Self {
+        let (_, visible_rows, hidden_rows) = rules.board_size();
+        let height = visible_rows + hidden_rows;
         // Create new tetris game
         // Create game field, functional style
         let field = (0..height)
             .map(|_| (0..width).map(|_| CellType::Empty).collect())
             .collect();
 
-        // Create preview field, functional style
-        let mut preview = (0..4)
-            .map(|_| (0..4).map(|_| CellType::Empty).collect())
-            .collect();
+        // Create preview field big enough for any piece of the set
+        let size = rules.pieces.preview_size();
+        let mut preview = vec![vec![CellType::Empty; size]; size];
 
         // Set next tetromino type
-        let next = Self::create_next_tetromino_type(&mut preview);
+        let next = Self::create_next_tetromino_type(&mut preview, &rules.pieces, &mut rng);
 
         // Create user actions queue
         let actions = VecDeque::new();
@@ -455,18 +604,27 @@ impl Tetris {
         Tetris {
             cols: width,
             rows: height,
+            hidden_rows,
             game_over,
             field,
             preview,
             current: None,
             next,
             actions,
+            system_actions: VecDeque::new(),
             drop: false,
-            game_speed: EventRegulator::new(1, 100),
-            drop_speed: EventRegulator::new(1, 10),
-            line_remove_speed: EventRegulator::new(3, 10),
+            game_speed: EventRegulator::new(rules.gravity.events, rules.gravity.steps),
+            drop_speed: EventRegulator::new(rules.drop_speed.events, rules.drop_speed.steps),
+            line_remove_speed: EventRegulator::new(
+                rules.blast_speed.events,
+                rules.blast_speed.steps,
+            ),
             line_remove_delay: None,
             score,
+            lines: 0,
+            level: 1,
+            rng,
+            rules: rules.clone(),
         }
     }
 
@@ -475,25 +633,27 @@ impl Tetris {
         self.actions.push_back(action);
     }
 
+    // Add system action to system actions queue
+    pub fn add_system_action(&mut self, action: SystemAction) {
+        self.system_actions.push_back(action);
+    }
+
     // Process single user action
     pub fn step(&mut self) -> StepResult {
         if self.game_over {
             return StepResult::GameOver;
         }
 
-        if let Some(ref mut delay) = self.line_remove_delay {
-            if *delay > 0 {
-                *delay -= 1;
-                return StepResult::None;
-            } else {
-                self.line_remove_delay = None;
-            }
+        if self.wait_line_remove_delay() {
+            return StepResult::None;
         }
         for _ in 0..self.line_remove_speed.step() {
             if self.current.is_none() {
-                if self.remove_top_blasted_line() {
+                if self.remove_top_blasted_line().is_some() {
                     return StepResult::LineRemoved;
                 } else {
+                    // System actions are applied between tetrominoes to not overlap falling one
+                    self.apply_system_actions();
                     if !self.place_next_tetromino() {
                         self.game_over = true;
                         return StepResult::GameOver;
@@ -502,15 +662,12 @@ impl Tetris {
             }
         }
 
-        if self.drop {
-            for _ in 0..self.drop_speed.step() {
-                self.actions.push_back(Action::MoveDown);
-            }
+        let speed = if self.drop {
+            &mut self.drop_speed
         } else {
-            for _ in 0..self.game_speed.step() {
-                self.actions.push_back(Action::MoveDown);
-            }
-        }
+            &mut self.game_speed
+        };
+        queue_gravity(&mut self.actions, speed);
 
         let Some(action) = self.actions.pop_front() else {
             return StepResult::None;
@@ -522,23 +679,91 @@ impl Tetris {
             Action::RotateLeft => self.rotate_left(),
             Action::RotateRight => self.rotate_right(),
             Action::Drop => self.drop(),
-            Action::BottomRefill => self.bottom_refill(),
         };
         // Move down is special case. If it fails, fix current tetromino and blast full lines
         if !succeed && action == Action::MoveDown {
-            self.fix_current_figure();
-            self.blast_full_lines();
+            if let Some(current) = self.current.take() {
+                if self.lock_piece(current).is_none() {
+                    return StepResult::GameOver;
+                }
+            }
             self.actions.clear();
-            self.line_remove_delay = Some(10); // Wait 10 ticks before placing next tetromino to show blast animation
+            // Wait before placing next tetromino to show blast animation
+            self.delay_line_remove();
         }
         return StepResult::ActionPerformed(action, succeed);
     }
 
-    // Create next tetromino type and draw it on preview field
-    fn create_next_tetromino_type(preview: &mut Vec<Vec<CellType>>) -> TetrominoType {
+    // Count down the delay before blasted lines are removed, true while it's not over
+    pub(crate) fn wait_line_remove_delay(&mut self) -> bool {
+        let Some(ref mut delay) = self.line_remove_delay else {
+            return false;
+        };
+        if *delay > 0 {
+            *delay -= 1;
+            return true;
+        }
+        self.line_remove_delay = None;
+        false
+    }
+
+    pub(crate) fn delay_line_remove(&mut self) {
+        self.line_remove_delay = Some(self.rules.line_remove_delay);
+    }
+
+    // Number of blasted lines to remove in this step
+    pub(crate) fn line_remove_steps(&mut self) -> usize {
+        self.line_remove_speed.step()
+    }
+
+    // Draw piece on the field, blast full lines and score them. Returns number of blasted
+    // lines, None if the game is over because the piece is fixed entirely above the visible area
+    pub(crate) fn lock_piece(&mut self, piece: Tetromino) -> Option<usize> {
+        piece.draw(&mut self.field);
+        let hidden_rows = self.hidden_rows as isize;
+        if piece.get_cells().iter().all(|(_, y)| *y < hidden_rows) {
+            self.game_over = true;
+            return None;
+        }
+        let lines = self.blast_full_lines();
+        self.lines += lines;
+        self.score += LINE_SCORES[lines.min(4)] * self.level;
+        Some(lines)
+    }
+
+    // Next tetromino type of the rules drawn on the given preview field
+    pub(crate) fn next_tetromino_type(
+        &mut self,
+        preview: &mut Vec<Vec<CellType>>,
+    ) -> TetrominoType {
+        Self::create_next_tetromino_type(preview, &self.rules.pieces, &mut self.rng)
+    }
+
+    pub(crate) fn set_game_over(&mut self) {
+        self.game_over = true;
+    }
+
+    // Apply all queued system actions
+    fn apply_system_actions(&mut self) {
+        while let Some(system_action) = self.system_actions.pop_front() {
+            match system_action {
+                SystemAction::BottomRefill => self.bottom_refill(),
+            };
+        }
+    }
+
+    // Create next tetromino type of the piece set and draw it on cleared preview field
+    fn create_next_tetromino_type(
+        preview: &mut Vec<Vec<CellType>>,
+        pieces: &PieceSet,
+        rng: &mut impl Rng,
+    ) -> TetrominoType {
         // Create next tetromino and draw it on preview field
         // Get next tetromino type
-        let tetromino_type = TetrominoType::new_random();
+        let tetromino_type = pieces.random(rng);
+        for row in preview.iter_mut() {
+            row.fill(CellType::Empty);
+        }
         // Create new tetromino
         let tetromino = Tetromino::new(tetromino_type, Rotation::R0, 0, 0);
         // Draw tetromino on preview field
@@ -551,6 +776,15 @@ impl Tetris {
         &self.field
     }
 
+    // Rows of the field without hidden ones
+    pub fn get_visible_field(&self) -> &[Vec<CellType>] {
+        &self.field[self.hidden_rows..]
+    }
+
+    pub fn get_hidden_rows(&self) -> usize {
+        self.hidden_rows
+    }
+
     pub fn get_current(&self) -> &Option<Tetromino> {
         &self.current
     }
@@ -559,20 +793,25 @@ impl Tetris {
         &self.next
     }
 
+    pub fn get_preview(&self) -> &Vec<Vec<CellType>> {
+        &self.preview
+    }
+
     // Place new tetromino on the field. Return false if it's impossible to place new tetromino
     pub fn place_next_tetromino(&mut self) -> bool {
         // Create new tetromino
-        let new_tetromino = Tetromino::new(self.next, Rotation::R0, self.cols as isize / 2 - 2, 0);
+        let new_tetromino = Tetromino::spawn(self.next, 0, self.cols, self.hidden_rows);
 
         // Check if new tetromino intersects with field borders or other tetrominos
-        if new_tetromino.intersects(&self.field) {
+        if new_tetromino.intersects(&self.field, &[]) {
             return false;
         }
         // Set new tetromino as current
         self.current = Some(new_tetromino);
 
         // Set next tetromino type and draw it on preview field
-        self.next = Self::create_next_tetromino_type(&mut self.preview);
+        self.next =
+            Self::create_next_tetromino_type(&mut self.preview, &self.rules.pieces, &mut self.rng);
 
         // Clear drop flag
         self.drop = false;
@@ -597,7 +836,7 @@ impl Tetris {
             current.y as isize + y,
         );
         // Check if new tetromino intersects with field borders or other tetrominos
-        if new_tetromino.intersects(&self.field) {
+        if new_tetromino.intersects(&self.field, &[]) {
             return false;
         }
         *current = new_tetromino;
@@ -649,10 +888,10 @@ impl Tetris {
                 self.field[y - 1][x] = self.field[y][x];
             }
         }
-        // Fill bottom line with random cells with probability of filled cell = 0.3
+        // Fill bottom line with random cells with probability of filled cell taken from the rules
         for x in 0..self.cols {
-            let cell_type = if rand::random::<f32>() < 0.5 {
-                CellType::new_random()
+            let cell_type = if self.rng.gen::<f32>() < self.rules.garbage.fill_chance {
+                CellType::new_random(&mut self.rng)
             } else {
                 CellType::Empty
             };
@@ -671,11 +910,11 @@ impl Tetris {
         }
     }
 
-    // Blasts full lines and returns true if there were full lines
-    fn blast_full_lines(&mut self) -> bool {
+    // Blasts full lines and returns number of them
+    fn blast_full_lines(&mut self) -> usize {
         // Iterate over all lines
-        // If line is full, replace it's Empty cells to Blasted cells and set return value to true
-        let mut full_lines = false;
+        // If line is full, replace it's Empty cells to Blasted cells and count it
+        let mut full_lines = 0;
         for y in 0..self.rows {
             let mut full_line = true;
             for x in 0..self.cols {
@@ -685,7 +924,7 @@ impl Tetris {
                 }
             }
             if full_line {
-                full_lines = true;
+                full_lines += 1;
                 for x in 0..self.cols {
                     self.field[y][x] = CellType::Blasted;
                 }
@@ -694,10 +933,14 @@ impl Tetris {
         full_lines
     }
 
+    pub(crate) fn has_blasted_lines(&self) -> bool {
+        self.field.iter().any(|row| row[0] == CellType::Blasted)
+    }
+
     // Find topmost blasted line and shift all lines above it down to one line
     // Line is blasted if it's first cell is Blasted
-    // Return false if there are no blasted lines
-    fn remove_top_blasted_line(&mut self) -> bool {
+    // Return index of removed line, None if there are no blasted lines
+    pub(crate) fn remove_top_blasted_line(&mut self) -> Option<usize> {
         // Find topmost blasted line
         let mut top_blasted_line = None;
         for y in 0..self.rows {
@@ -706,10 +949,8 @@ impl Tetris {
                 break;
             }
         }
-        // If there are no blasted lines, return false
-        let Some(top_blasted_line) = top_blasted_line else {
-            return false;
-        };
+        // If there are no blasted lines, return None
+        let top_blasted_line = top_blasted_line?;
         // Shift all lines above topmost blasted line down to one line
         for y in (0..top_blasted_line).rev() {
             for x in 0..self.cols {
@@ -720,21 +961,21 @@ impl Tetris {
         for x in 0..self.cols {
             self.field[0][x] = CellType::Empty;
         }
-        // Return true if there were blasted lines
-        true
+        Some(top_blasted_line)
     }
 
-    // get game state for serialization
+    // get game state for serialization, hidden rows are not included
     pub fn get_game_state(&self) -> TetrisGameState {
         let mut field = self.field.clone();
         // draw current tetromino on the field
         if let Some(current) = &self.current {
             current.draw(&mut field);
         }
+        field.drain(..self.hidden_rows);
         let preview = self.preview.clone();
         TetrisGameState {
             cols: self.cols,
-            rows: self.rows,
+            rows: field.len(),
             field,
             preview,
             game_over: self.game_over,
@@ -744,6 +985,33 @@ impl Tetris {
     pub fn is_game_over(&self) -> bool {
         self.game_over
     }
+
+    pub fn get_score(&self) -> usize {
+        self.score
+    }
+
+    pub fn get_lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn get_level(&self) -> usize {
+        self.level
+    }
+
+    pub fn get_rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    // Set game level and gravity for it. Time to fall one row is taken from the Tetris guideline:
+    // (0.8 - (level - 1) * 0.007) ^ (level - 1) seconds
+    pub fn set_level(&mut self, level: usize) {
+        let level = level.max(1);
+        let seconds_per_row = (0.8 - (level - 1) as f64 * 0.007).powi(level as i32 - 1);
+        // Rows per 1000 steps of 10 ms
+        let rows = (10.0 / seconds_per_row).round().max(1.0) as usize;
+        self.game_speed.set_mn(rows, 1000);
+        self.level = level;
+    }
 }
 
 #[derive(Serialize)]
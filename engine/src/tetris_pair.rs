@@ -0,0 +1,224 @@
+use crate::{
+    lifecycle::Lifecycle,
+    matches::PlayerSide,
+    pause::Pause,
+    rules::RuleSet,
+    tetris::{Action, StepResult, SystemAction, Tetris, TetrisGameState},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub struct TetrisPairState {
+    pub player: TetrisGameState,
+    pub opponent: TetrisGameState,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct TetrisPair {
+    tetris_a: Tetris,
+    tetris_b: Tetris,
+    // The step is performed when both players have called step method
+    // This is to prevent one player from getting an advantage by calling step more often
+    step_a: bool,
+    step_b: bool,
+    step_divergence: usize,
+    // Boards are stepped only while the match is playing, see Lifecycle
+    lifecycle: Lifecycle,
+    // Boards are not stepped while the match is paused
+    pause: Pause,
+}
+
+impl TetrisPair {
+    pub fn new(rules: &RuleSet) -> TetrisPair {
+        TetrisPair {
+            tetris_a: Tetris::new(rules),
+            tetris_b: Tetris::new(rules),
+            step_a: false,
+            step_b: false,
+            step_divergence: 0,
+            lifecycle: Lifecycle::default(),
+            pause: Pause::new(rules.pauses),
+        }
+    }
+
+    // Create pair of boards with the same fixed sequence of tetrominoes
+    pub fn with_seed(rules: &RuleSet, seed: u64) -> TetrisPair {
+        TetrisPair {
+            tetris_a: Tetris::with_seed(rules, seed),
+            tetris_b: Tetris::with_seed(rules, seed),
+            step_a: false,
+            step_b: false,
+            step_divergence: 0,
+            lifecycle: Lifecycle::default(),
+            pause: Pause::new(rules.pauses),
+        }
+    }
+
+    pub fn step_player(&mut self, player: PlayerSide) -> usize {
+        match player {
+            PlayerSide::A => self.step_a = true,
+            PlayerSide::B => self.step_b = true,
+        }
+        if self.step_a && self.step_b {
+            self.step_divergence = 0;
+            self.step_a = false;
+            self.step_b = false;
+            if !self.lifecycle.step() || !self.pause.step() {
+                return self.step_divergence;
+            }
+            let step_result_a = self.tetris_a.step();
+            let step_result_b = self.tetris_b.step();
+            if step_result_a == StepResult::LineRemoved {
+                Self::send_garbage(&mut self.tetris_b);
+            }
+            if step_result_b == StepResult::LineRemoved {
+                Self::send_garbage(&mut self.tetris_a);
+            }
+            if self.is_game_over() {
+                self.lifecycle.finish();
+            }
+        } else {
+            self.step_divergence += 1;
+        }
+        self.step_divergence
+    }
+
+    // Both boards are created with the same rules
+    pub fn get_rules(&self) -> &RuleSet {
+        self.tetris_a.get_rules()
+    }
+
+    // Push garbage rows to the board of the player who didn't remove the line
+    fn send_garbage(tetris: &mut Tetris) {
+        for _ in 0..tetris.get_rules().garbage.rows_per_line {
+            tetris.add_system_action(SystemAction::BottomRefill);
+        }
+    }
+
+    // Actions are dropped before the match starts and while it's paused
+    pub fn add_player_action(&mut self, player: PlayerSide, action: Action) {
+        if !self.lifecycle.is_playing() || self.pause.is_paused() {
+            return;
+        }
+        match player {
+            PlayerSide::A => self.tetris_a.add_action(action),
+            PlayerSide::B => self.tetris_b.add_action(action),
+        }
+    }
+
+    // Returns true if the match is paused, see Pause. Only running match can be paused
+    pub fn request_pause(&mut self, player: PlayerSide) -> bool {
+        self.lifecycle.is_playing() && self.pause.request(player)
+    }
+
+    pub fn resume(&mut self) {
+        self.pause.resume();
+    }
+
+    pub fn get_pause(&self) -> &Pause {
+        &self.pause
+    }
+
+    // Player confirms being ready to start the match
+    pub fn set_ready(&mut self, player: PlayerSide) {
+        self.lifecycle.set_ready(player);
+    }
+
+    // Start at once without ready check, used for matches between computer players
+    pub fn start(&mut self) {
+        self.lifecycle.start();
+    }
+
+    // Returns true when both players have seen the finished match, see Lifecycle
+    pub fn see_finished(&mut self, player: PlayerSide) -> bool {
+        self.lifecycle.see_finished(player)
+    }
+
+    pub fn get_lifecycle(&self) -> &Lifecycle {
+        &self.lifecycle
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.tetris_a.is_game_over() || self.tetris_b.is_game_over()
+    }
+
+    // Get player's and opponent's boards
+    pub fn get_player_tetris(&self, player: PlayerSide) -> (&Tetris, &Tetris) {
+        match player {
+            PlayerSide::A => (&self.tetris_a, &self.tetris_b),
+            PlayerSide::B => (&self.tetris_b, &self.tetris_a),
+        }
+    }
+
+    pub fn get_player_game_state(&self, player: PlayerSide) -> TetrisPairState {
+        match player {
+            PlayerSide::A => TetrisPairState {
+                player: self.tetris_a.get_game_state(),
+                opponent: self.tetris_b.get_game_state(),
+            },
+            PlayerSide::B => TetrisPairState {
+                player: self.tetris_b.get_game_state(),
+                opponent: self.tetris_a.get_game_state(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::{Bot, BotDifficulty};
+
+    // Step both boards, bots play both sides
+    fn step(pair: &mut TetrisPair, bots: &mut [Bot; 2]) {
+        for (side, bot) in [PlayerSide::A, PlayerSide::B]
+            .into_iter()
+            .zip(bots.iter_mut())
+        {
+            let (tetris, _) = pair.get_player_tetris(side);
+            if let Some(action) = bot.think(tetris) {
+                pair.add_player_action(side, action);
+            }
+            pair.step_player(side);
+        }
+    }
+
+    fn state(pair: &TetrisPair) -> serde_json::Value {
+        serde_json::to_value(pair).unwrap()
+    }
+
+    #[test]
+    fn snapshot_resumes_identically() {
+        let rules = RuleSet::guideline();
+        let mut pair = TetrisPair::with_seed(&rules, 7);
+        pair.start();
+        let mut bots = [
+            Bot::with_seed(BotDifficulty::MEDIUM, 1),
+            Bot::with_seed(BotDifficulty::MEDIUM, 2),
+        ];
+        for _ in 0..3000 {
+            step(&mut pair, &mut bots);
+        }
+        // Snapshot is taken with actions and garbage waiting in the queues
+        pair.add_player_action(PlayerSide::A, Action::MoveLeft);
+        pair.add_player_action(PlayerSide::A, Action::RotateRight);
+        pair.add_player_action(PlayerSide::B, Action::Drop);
+        pair.tetris_b.add_system_action(SystemAction::BottomRefill);
+        assert!(!pair.is_game_over());
+
+        let snapshot = serde_json::to_string(&(&pair, &bots)).unwrap();
+        let (mut resumed, mut resumed_bots): (TetrisPair, [Bot; 2]) =
+            serde_json::from_str(&snapshot).unwrap();
+        assert_eq!(state(&pair), state(&resumed));
+
+        for _ in 0..5000 {
+            step(&mut pair, &mut bots);
+            step(&mut resumed, &mut resumed_bots);
+        }
+        assert_eq!(state(&pair), state(&resumed));
+        assert_eq!(
+            serde_json::to_value(&bots).unwrap(),
+            serde_json::to_value(&resumed_bots).unwrap()
+        );
+    }
+}
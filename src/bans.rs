@@ -0,0 +1,89 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::sync::RwLock;
+
+use persy::{Persy, ValueMode};
+
+use crate::clock::now;
+use crate::error::Error;
+
+// Persy indexes with banned user ids and ip addresses, value is ban time in seconds since epoch
+const BANNED_USERS_INDEX: &str = "banned_users";
+const BANNED_IPS_INDEX: &str = "banned_ips";
+
+//
+// Banned identities and ip addresses. Stored in Persy and cached in memory
+// to be checked on every request
+//
+pub struct Bans {
+    persy: Persy,
+    users: RwLock<HashSet<u32>>,
+    ips: RwLock<HashSet<IpAddr>>,
+}
+
+impl Bans {
+    // Create indexes if they don't exist yet and load bans from database
+    pub fn open(persy: Persy) -> Result<Bans, Error> {
+        let mut tx = persy.begin()?;
+        if !tx.exists_index(BANNED_USERS_INDEX)? {
+            tx.create_index::<u32, u64>(BANNED_USERS_INDEX, ValueMode::Replace)?;
+        }
+        if !tx.exists_index(BANNED_IPS_INDEX)? {
+            tx.create_index::<String, u64>(BANNED_IPS_INDEX, ValueMode::Replace)?;
+        }
+        tx.prepare()?.commit()?;
+
+        let users = persy
+            .range::<u32, u64, _>(BANNED_USERS_INDEX, ..)?
+            .map(|(user_id, _)| user_id)
+            .collect();
+        // Skip addresses which can't be parsed instead of failing to start
+        let ips = persy
+            .range::<String, u64, _>(BANNED_IPS_INDEX, ..)?
+            .filter_map(|(ip, _)| ip.parse().ok())
+            .collect();
+
+        Ok(Bans {
+            persy,
+            users: RwLock::new(users),
+            ips: RwLock::new(ips),
+        })
+    }
+
+    pub fn is_banned(&self, user_id: u32, ip: Option<IpAddr>) -> bool {
+        self.users.read().unwrap().contains(&user_id)
+            || ip.is_some_and(|ip| self.ips.read().unwrap().contains(&ip))
+    }
+
+    pub fn ban_user(&self, user_id: u32) -> Result<(), Error> {
+        let mut tx = self.persy.begin()?;
+        tx.put::<u32, u64>(BANNED_USERS_INDEX, user_id, now())?;
+        tx.prepare()?.commit()?;
+        self.users.write().unwrap().insert(user_id);
+        Ok(())
+    }
+
+    pub fn unban_user(&self, user_id: u32) -> Result<(), Error> {
+        let mut tx = self.persy.begin()?;
+        tx.remove::<u32, u64>(BANNED_USERS_INDEX, user_id, None)?;
+        tx.prepare()?.commit()?;
+        self.users.write().unwrap().remove(&user_id);
+        Ok(())
+    }
+
+    pub fn ban_ip(&self, ip: IpAddr) -> Result<(), Error> {
+        let mut tx = self.persy.begin()?;
+        tx.put::<String, u64>(BANNED_IPS_INDEX, ip.to_string(), now())?;
+        tx.prepare()?.commit()?;
+        self.ips.write().unwrap().insert(ip);
+        Ok(())
+    }
+
+    pub fn unban_ip(&self, ip: IpAddr) -> Result<(), Error> {
+        let mut tx = self.persy.begin()?;
+        tx.remove::<String, u64>(BANNED_IPS_INDEX, ip.to_string(), None)?;
+        tx.prepare()?.commit()?;
+        self.ips.write().unwrap().remove(&ip);
+        Ok(())
+    }
+}
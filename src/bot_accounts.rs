@@ -0,0 +1,52 @@
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+use persy::{Persy, ValueMode};
+
+use crate::clock::now;
+use crate::error::Error;
+
+// Persy index with user ids of bot accounts, value is registration time in seconds since epoch
+const BOT_ACCOUNTS_INDEX: &str = "bot_accounts";
+
+//
+// Users registered as external AI players. Their results are flagged,
+// so they can be told apart from human players
+//
+pub struct BotAccounts {
+    persy: Persy,
+    users: RwLock<HashSet<u32>>,
+}
+
+impl BotAccounts {
+    // Create index if it doesn't exist yet and load bot accounts from database
+    pub fn open(persy: Persy) -> Result<BotAccounts, Error> {
+        let mut tx = persy.begin()?;
+        if !tx.exists_index(BOT_ACCOUNTS_INDEX)? {
+            tx.create_index::<u32, u64>(BOT_ACCOUNTS_INDEX, ValueMode::Replace)?;
+        }
+        tx.prepare()?.commit()?;
+
+        let users = persy
+            .range::<u32, u64, _>(BOT_ACCOUNTS_INDEX, ..)?
+            .map(|(user_id, _)| user_id)
+            .collect();
+
+        Ok(BotAccounts {
+            persy,
+            users: RwLock::new(users),
+        })
+    }
+
+    pub fn is_bot(&self, user_id: u32) -> bool {
+        self.users.read().unwrap().contains(&user_id)
+    }
+
+    pub fn register(&self, user_id: u32) -> Result<(), Error> {
+        let mut tx = self.persy.begin()?;
+        tx.put::<u32, u64>(BOT_ACCOUNTS_INDEX, user_id, now())?;
+        tx.prepare()?.commit()?;
+        self.users.write().unwrap().insert(user_id);
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+
+use rocket::serde::json::serde_json;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::MatchEvent;
+use crate::solo::SoloResult;
+use tetris_engine::bot::{legal_placements, Placement};
+use tetris_engine::tetris::{Action, CellType, Tetris, Tetromino, TetrominoType};
+
+//
+// Protocol for external AI players. Bot accounts are registered with
+// `POST /bot/register`, which marks user of the `user_id` cookie as a bot.
+// Bots play over WebSocket:
+//   /bot/ws                 - match against humans or other bots, using the common wait list
+//   /bot/solo/<mode>/ws     - single player game, mode is marathon, sprint or ultra
+//
+// Each message is a JSON object with "type" field. Server sends "state" message
+// whenever any board changes:
+//   {"type": "state", "board": {...}, "opponent": {...}, "placements": [...]}
+// where board contains raw field as rows of cell types (0 is empty), current piece
+// {"tetromino_type", "rotation", "x", "y"} or null between pieces, next piece type,
+// score, lines, level and game_over flag. Opponent is absent in single player game.
+// Placements are all final positions {"rotation", "x"} reachable by rotating current
+// piece in place, moving it horizontally and dropping it.
+//
+// Bot sends either low-level action, e.g. {"type": "action", "action": "MoveLeft"},
+// or target placement {"type": "place", "rotation": "R90", "x": 3}, which is converted
+// to rotation, moves and drop. Placement not in the list is answered with "error" message.
+// "match_event" and "solo_finished" messages are the same as for human players.
+//
+
+// Raw board state
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BotBoard {
+    cols: usize,
+    // Field rows including hidden ones above the visible area
+    rows: usize,
+    hidden_rows: usize,
+    field: Vec<Vec<CellType>>,
+    current: Option<Tetromino>,
+    next: TetrominoType,
+    score: usize,
+    lines: usize,
+    level: usize,
+    game_over: bool,
+}
+
+impl BotBoard {
+    pub fn new(tetris: &Tetris) -> Self {
+        let field = tetris.get_field().clone();
+        BotBoard {
+            cols: field.first().map_or(0, |row| row.len()),
+            rows: field.len(),
+            hidden_rows: tetris.get_hidden_rows(),
+            field,
+            current: *tetris.get_current(),
+            next: *tetris.get_next(),
+            score: tetris.get_score(),
+            lines: tetris.get_lines(),
+            level: tetris.get_level(),
+            game_over: tetris.is_game_over(),
+        }
+    }
+
+    fn placements(&self) -> Vec<Placement> {
+        self.current.as_ref().map_or_else(Vec::new, |current| {
+            legal_placements(&self.field, current)
+                .into_iter()
+                .map(|(placement, _)| placement)
+                .collect()
+        })
+    }
+}
+
+// Message sent by bot
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotCommand {
+    Action { action: Action },
+    Place(Placement),
+}
+
+impl BotCommand {
+    // Actions performing the command on the board
+    pub fn actions(&self, tetris: &Tetris) -> Result<VecDeque<Action>, String> {
+        match self {
+            BotCommand::Action { action } => Ok(VecDeque::from([*action])),
+            BotCommand::Place(placement) => {
+                let Some(current) = tetris.get_current() else {
+                    return Err("No piece to place".to_string());
+                };
+                let legal = legal_placements(tetris.get_field(), current)
+                    .iter()
+                    .any(|(legal, _)| legal == placement);
+                if legal {
+                    Ok(placement.actions(current))
+                } else {
+                    Err("Illegal placement".to_string())
+                }
+            }
+        }
+    }
+}
+
+// Message sent to bot
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage<'a> {
+    State {
+        board: &'a BotBoard,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        opponent: Option<&'a BotBoard>,
+        placements: Vec<Placement>,
+    },
+    MatchEvent {
+        event: MatchEvent,
+    },
+    SoloFinished {
+        result: &'a SoloResult,
+    },
+    Error {
+        message: String,
+    },
+}
+
+// Produces state messages for single bot, remembers last state sent to it
+#[derive(Default)]
+pub struct BotStateEncoder {
+    last: Option<(BotBoard, Option<BotBoard>)>,
+}
+
+impl BotStateEncoder {
+    pub fn new() -> Self {
+        BotStateEncoder::default()
+    }
+
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    // Encode boards state as JSON message. Returns None if nothing changed since last message
+    pub fn encode(&mut self, board: &Tetris, opponent: Option<&Tetris>) -> Option<String> {
+        let boards = (BotBoard::new(board), opponent.map(BotBoard::new));
+        if self.last.as_ref() == Some(&boards) {
+            return None;
+        }
+        let (board, opponent) = &boards;
+        let message = BotMessage::State {
+            board,
+            opponent: opponent.as_ref(),
+            placements: board.placements(),
+        };
+        let message = serde_json::to_string(&message).unwrap();
+        self.last = Some(boards);
+        Some(message)
+    }
+}
@@ -5,10 +5,12 @@ use persy::PersyError;
 pub enum Error {
     // Error type for Persy database errors
     PersyDatabaseError(persy::PersyError),
-    // Error type for rocket errors
-    RocketError(rocket::Error),
+    // Error type for rocket errors, boxed because it's much larger than others
+    RocketError(Box<rocket::Error>),
     // Error type for io::Result errors
     IoError(std::io::Error),
+    // Invalid settings in Rocket.toml or environment
+    ConfigError(Box<rocket::figment::Error>),
 }
 
 impl<T: Into<PersyError>> From<persy::PE<T>> for Error {
@@ -27,7 +29,7 @@ impl From<persy::PersyError> for Error {
 
 impl From<rocket::Error> for Error {
     fn from(err: rocket::Error) -> Self {
-        Error::RocketError(err)
+        Error::RocketError(Box::new(err))
     }
 }
 
@@ -37,6 +39,12 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<rocket::figment::Error> for Error {
+    fn from(err: rocket::figment::Error) -> Self {
+        Error::ConfigError(Box::new(err))
+    }
+}
+
 // Implement display trait for error type
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -44,6 +52,7 @@ impl std::fmt::Display for Error {
             Error::PersyDatabaseError(err) => write!(f, "Persy database error: {}", err),
             Error::RocketError(err) => write!(f, "Rocket error: {}", err),
             Error::IoError(err) => write!(f, "Io error: {}", err),
+            Error::ConfigError(err) => write!(f, "Config error: {}", err),
         }
     }
 }
@@ -0,0 +1,255 @@
+use serde::Serialize;
+
+use tetris_engine::tetris::{CellType, Tetris, Tetromino};
+use tetris_engine::CoopTetris;
+
+//
+// Delta compressed game state stream. First frame is a keyframe with full state of all boards,
+// following frames contain only changed cells and new position of the falling piece.
+// Frames are numbered, client which missed a frame should request a resync to get a new keyframe.
+// Player's own board goes first, e.g. player and opponent boards in a match
+//
+
+// Board state with falling piece kept apart from fixed cells
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BoardFrame {
+    cols: usize,
+    rows: usize,
+    field: Vec<Vec<CellType>>,
+    piece: Option<PieceCells>,
+    preview: Vec<Vec<CellType>>,
+    game_over: bool,
+}
+
+// Cells occupied by falling piece
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PieceCells {
+    cell_type: CellType,
+    // Field positions (x, y)
+    cells: Vec<(isize, isize)>,
+}
+
+impl PieceCells {
+    // Cells of the piece in the visible area, positions are relative to it
+    fn visible(piece: &Tetromino, hidden_rows: usize) -> Self {
+        let hidden_rows = hidden_rows as isize;
+        PieceCells {
+            cell_type: piece.get_cell_type(),
+            cells: piece
+                .get_cells()
+                .into_iter()
+                .filter(|(_, y)| *y >= hidden_rows)
+                .map(|(x, y)| (x, y - hidden_rows))
+                .collect(),
+        }
+    }
+}
+
+impl BoardFrame {
+    // Only visible area of the board is sent, hidden rows above it are left out
+    pub fn new(tetris: &Tetris) -> Self {
+        let field = tetris.get_visible_field().to_vec();
+        BoardFrame {
+            cols: field.first().map_or(0, |row| row.len()),
+            rows: field.len(),
+            field,
+            piece: tetris
+                .get_current()
+                .map(|current| PieceCells::visible(&current, tetris.get_hidden_rows())),
+            preview: tetris.get_preview().clone(),
+            game_over: tetris.is_game_over(),
+        }
+    }
+
+    // Shared board of co-op game from player's point of view. Pieces of other players
+    // are drawn on the field, so clients show them without knowing about co-op
+    pub fn coop(tetris: &CoopTetris, player: usize) -> Self {
+        let mut field = tetris.get_field().clone();
+        for (i, piece) in (0..tetris.players()).filter_map(|i| Some((i, tetris.get_piece(i)?))) {
+            if i != player {
+                piece.draw(&mut field);
+            }
+        }
+        field.drain(..tetris.get_hidden_rows());
+        BoardFrame {
+            cols: field.first().map_or(0, |row| row.len()),
+            rows: field.len(),
+            field,
+            piece: tetris
+                .get_piece(player)
+                .map(|current| PieceCells::visible(&current, tetris.get_hidden_rows())),
+            preview: tetris.get_preview(player).cloned().unwrap_or_default(),
+            game_over: tetris.is_game_over(),
+        }
+    }
+
+    // Get changes from previous state of the board. Returns None if board size differs
+    fn delta(&self, prev: &BoardFrame) -> Option<BoardDelta> {
+        if self.cols != prev.cols || self.rows != prev.rows {
+            return None;
+        }
+        let mut cells = Vec::new();
+        for (y, (row, prev_row)) in self.field.iter().zip(&prev.field).enumerate() {
+            for (x, (cell, prev_cell)) in row.iter().zip(prev_row).enumerate() {
+                if cell != prev_cell {
+                    cells.push((x, y, *cell));
+                }
+            }
+        }
+        Some(BoardDelta {
+            cells,
+            piece: (self.piece != prev.piece).then(|| self.piece.clone()),
+            preview: (self.preview != prev.preview).then(|| self.preview.clone()),
+            game_over: (self.game_over != prev.game_over).then_some(self.game_over),
+        })
+    }
+}
+
+// Changes of the board. Absent fields are not changed
+#[derive(Debug, Serialize)]
+pub struct BoardDelta {
+    // Changed fixed cells (x, y, cell type)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cells: Vec<(usize, usize, CellType)>,
+    // New falling piece, null if piece was fixed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    piece: Option<Option<PieceCells>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview: Option<Vec<Vec<CellType>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    game_over: Option<bool>,
+}
+
+impl BoardDelta {
+    fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+            && self.piece.is_none()
+            && self.preview.is_none()
+            && self.game_over.is_none()
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "frame", rename_all = "snake_case")]
+pub enum Frame {
+    Key { seq: u64, boards: Vec<BoardFrame> },
+    Delta { seq: u64, boards: Vec<BoardDelta> },
+}
+
+// Produces frames for single client, remembers last state sent to it
+#[derive(Default)]
+pub struct FrameEncoder {
+    seq: u64,
+    last: Option<Vec<BoardFrame>>,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Self {
+        FrameEncoder::default()
+    }
+
+    // Send keyframe next time
+    pub fn resync(&mut self) {
+        self.last = None;
+    }
+
+    // Encode boards state. Returns None if nothing changed since last frame
+    pub fn encode(&mut self, boards: Vec<BoardFrame>) -> Option<Frame> {
+        let deltas = self
+            .last
+            .as_ref()
+            .filter(|last| last.len() == boards.len())
+            .and_then(|last| {
+                boards
+                    .iter()
+                    .zip(last)
+                    .map(|(board, last_board)| board.delta(last_board))
+                    .collect::<Option<Vec<_>>>()
+            });
+        let frame = match deltas {
+            Some(deltas) if deltas.iter().all(BoardDelta::is_empty) => return None,
+            Some(deltas) => Frame::Delta {
+                seq: self.seq,
+                boards: deltas,
+            },
+            None => Frame::Key {
+                seq: self.seq,
+                boards: boards.clone(),
+            },
+        };
+        self.seq += 1;
+        self.last = Some(boards);
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::{serde_json, Value};
+    use tetris_engine::{Action, RuleSet};
+
+    // Apply delta frame to boards of the last frame, same as the clients do
+    fn apply(boards: &mut Value, deltas: &Value) {
+        let boards = boards.as_array_mut().unwrap();
+        for (board, delta) in boards.iter_mut().zip(deltas.as_array().unwrap()) {
+            for cell in delta["cells"].as_array().into_iter().flatten() {
+                let x = cell[0].as_u64().unwrap() as usize;
+                let y = cell[1].as_u64().unwrap() as usize;
+                board["field"][y][x] = cell[2].clone();
+            }
+            for key in ["piece", "preview", "game_over"] {
+                if let Some(value) = delta.get(key) {
+                    board[key] = value.clone();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deltas_reproduce_keyframes() {
+        let actions = [
+            Action::MoveLeft,
+            Action::RotateRight,
+            Action::Drop,
+            Action::MoveRight,
+            Action::MoveRight,
+            Action::RotateLeft,
+            Action::Drop,
+        ];
+        // Player's board is played, opponent's one only falls until it's topped out
+        let mut player = Tetris::with_seed(&RuleSet::classic(), 1);
+        let mut opponent = Tetris::with_seed(&RuleSet::classic(), 2);
+        let mut encoder = FrameEncoder::new();
+        let mut boards = Value::Null;
+        let (mut deltas_count, mut fixed, mut previews) = (0, 0, 0);
+        for step in 0..20000 {
+            if step % 5 == 0 {
+                player.add_action(actions[step / 5 % actions.len()]);
+            }
+            if step == 10000 {
+                encoder.resync();
+            }
+            player.step();
+            opponent.step();
+            let frames = vec![BoardFrame::new(&player), BoardFrame::new(&opponent)];
+            let keyframe = serde_json::to_value(&frames).unwrap();
+            match encoder.encode(frames) {
+                Some(Frame::Key { boards: key, .. }) => boards = serde_json::to_value(key).unwrap(),
+                Some(Frame::Delta { boards: deltas, .. }) => {
+                    let deltas = serde_json::to_value(deltas).unwrap();
+                    for delta in deltas.as_array().unwrap() {
+                        fixed += delta.get("piece").is_some_and(Value::is_null) as usize;
+                        previews += delta.get("preview").is_some() as usize;
+                    }
+                    apply(&mut boards, &deltas);
+                    deltas_count += 1;
+                }
+                None => {}
+            }
+            assert_eq!(boards, keyframe, "step {}", step);
+        }
+        assert!(deltas_count > 0 && fixed > 0 && previews > 0);
+        assert!(opponent.is_game_over());
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use rocket::futures::FutureExt;
+use rocket::Shutdown;
+use tetris_engine::{Action, GroupField, GroupMatch, GroupMatches, MatchId, PlayerStatus, RuleSet};
+
+use crate::bot_accounts::BotAccounts;
+use crate::frames::{Frame, FrameEncoder};
+use crate::match_records::AbortedGroupMatch;
+use crate::protocol::GroupInfo;
+use crate::settings::Settings;
+
+// Player who didn't step the game for this time is considered gone. Longer than
+// for duels, since players in the lobby step once per second
+const GROUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+//
+// Matches of many players: battle royale and team matches. Players wait in the lobby
+// until it's full or enough players waited long enough. Matches don't wait for
+// disconnected players to return, player who stopped stepping forfeits
+//
+pub struct GroupGames<F> {
+    games: RwLock<GroupMatches<u32, F>>,
+    // Time when the lobby got enough players to start a match
+    lobby_since: Mutex<Option<Instant>>,
+    // Time of the last step by each player
+    last_seen: Mutex<HashMap<u32, Instant>>,
+    // Players removed by admin whose streams are not closed yet
+    kicked: Mutex<HashSet<u32>>,
+    min_players: usize,
+    max_players: usize,
+    lobby_wait: Duration,
+    rules: RuleSet,
+    // Field of a new match for the number of players
+    new_field: fn(&RuleSet, usize) -> F,
+    pub tick: Duration,
+}
+
+impl<F: GroupField> GroupGames<F> {
+    pub fn new(
+        settings: &Settings,
+        min_players: usize,
+        max_players: usize,
+        lobby_wait: Duration,
+        new_field: fn(&RuleSet, usize) -> F,
+    ) -> Self {
+        GroupGames {
+            games: RwLock::new(GroupMatches::new()),
+            lobby_since: Mutex::new(None),
+            last_seen: Mutex::new(HashMap::new()),
+            kicked: Mutex::new(HashSet::new()),
+            min_players: min_players.min(max_players),
+            max_players,
+            lobby_wait,
+            rules: settings.rules.clone(),
+            new_field,
+            tick: settings.tick(),
+        }
+    }
+    // Step player's match and get result of `f` applied to it. Player who is not
+    // in a match joins the lobby, None is returned until the match starts
+    pub fn step<R>(
+        &self,
+        user_id: u32,
+        f: impl FnOnce(&GroupMatch<u32, F>, usize) -> R,
+    ) -> Option<R> {
+        let mut games = self.games.write().unwrap();
+        let mut last_seen = self.last_seen.lock().unwrap();
+        last_seen.insert(user_id, Instant::now());
+        games.join(user_id);
+        if games.get_player_status(&user_id) == PlayerStatus::WaitList {
+            self.start_match(&mut games, &mut last_seen);
+        }
+        let (_, game) = games.get_mut_match_for_player(&user_id)?;
+        let gone: Vec<u32> = game
+            .players
+            .iter()
+            .filter(|p| {
+                last_seen
+                    .get(p)
+                    .is_none_or(|seen| seen.elapsed() > GROUP_TIMEOUT)
+            })
+            .copied()
+            .collect();
+        for player in &gone {
+            if let Some(index) = game.get_player_index(player) {
+                game.field.forfeit(index);
+            }
+        }
+        for player in &gone {
+            games.leave_match(player);
+        }
+        let (_, game) = games.get_mut_match_for_player(&user_id)?;
+        let index = game.get_player_index(&user_id)?;
+        game.field.step_player(index);
+        Some(f(game, index))
+    }
+    // Start match if there are enough players in the lobby
+    fn start_match(&self, games: &mut GroupMatches<u32, F>, last_seen: &mut HashMap<u32, Instant>) {
+        // Players who closed their streams are not counted
+        let stale: Vec<u32> = last_seen
+            .iter()
+            .filter(|(_, seen)| seen.elapsed() > GROUP_TIMEOUT)
+            .map(|(user_id, _)| *user_id)
+            .collect();
+        for user_id in stale {
+            if games.get_player_status(&user_id) == PlayerStatus::WaitList {
+                games.leave_lobby(&user_id);
+                last_seen.remove(&user_id);
+            }
+        }
+        let mut lobby_since = self.lobby_since.lock().unwrap();
+        if games.lobby_len() < self.min_players {
+            *lobby_since = None;
+            return;
+        }
+        let since = *lobby_since.get_or_insert_with(Instant::now);
+        if games.lobby_len() >= self.max_players || since.elapsed() >= self.lobby_wait {
+            games.start_match(self.max_players, |players| {
+                (self.new_field)(&self.rules, players)
+            });
+            *lobby_since = None;
+        }
+    }
+    pub fn lobby_len(&self) -> usize {
+        self.games.read().unwrap().lobby_len()
+    }
+    // Returns false if player is not in a match
+    pub fn add_action(&self, user_id: u32, action: Action) -> bool {
+        self.with_field(user_id, |field, index| {
+            field.add_player_action(index, action)
+        })
+    }
+    // Apply `f` to the field of player's match and player's index. Returns false if player is not in a match
+    pub fn with_field(&self, user_id: u32, f: impl FnOnce(&mut F, usize)) -> bool {
+        let mut games = self.games.write().unwrap();
+        let Some((_, game)) = games.get_mut_match_for_player(&user_id) else {
+            return false;
+        };
+        if let Some(index) = game.get_player_index(&user_id) {
+            f(&mut game.field, index);
+        }
+        true
+    }
+    // Player leaves finished match or the lobby, forfeits if the match is still running
+    pub fn leave(&self, user_id: u32) {
+        let mut games = self.games.write().unwrap();
+        if let Some((_, game)) = games.get_mut_match_for_player(&user_id) {
+            if let Some(index) = game.get_player_index(&user_id) {
+                game.field.forfeit(index);
+            }
+        }
+        games.leave_match(&user_id);
+        games.leave_lobby(&user_id);
+        self.last_seen.lock().unwrap().remove(&user_id);
+    }
+    // Remove player from the lobby or the match and close player's stream.
+    // Returns false if player is not found
+    pub fn kick(&self, user_id: u32) -> bool {
+        let found =
+            self.games.read().unwrap().get_player_status(&user_id) != PlayerStatus::NotFound;
+        if found {
+            self.leave(user_id);
+            self.kicked.lock().unwrap().insert(user_id);
+        }
+        found
+    }
+    // Check and clear player's kick mark
+    pub fn take_kicked(&self, user_id: u32) -> bool {
+        self.kicked.lock().unwrap().remove(&user_id)
+    }
+    // Remove all matches, returns their records. `kind` names the kind of matches in records
+    pub fn abort_all(&self, kind: &str, bot_accounts: &BotAccounts) -> Vec<AbortedGroupMatch> {
+        let mut games = self.games.write().unwrap();
+        let match_ids: Vec<MatchId> = games.iter().map(|(match_id, _)| match_id).collect();
+        match_ids
+            .into_iter()
+            .filter_map(|match_id| games.remove_match(match_id))
+            .map(|game| {
+                let is_bot = |user_id| bot_accounts.is_bot(user_id);
+                AbortedGroupMatch::new(kind, &self.rules.name, game.players, is_bot, &game.field)
+            })
+            .collect()
+    }
+}
+
+// Player's match, player leaves it when the stream is closed. On server shutdown
+// the match is kept to be recorded as aborted
+pub struct GroupGuard<'a, F: GroupField> {
+    pub games: &'a GroupGames<F>,
+    pub user_id: u32,
+    pub shutdown: Shutdown,
+}
+
+impl<F: GroupField> Drop for GroupGuard<'_, F> {
+    fn drop(&mut self) {
+        if self.shutdown.clone().now_or_never().is_none() {
+            self.games.leave(self.user_id);
+        }
+    }
+}
+
+// Player's step of the match with match state of type I
+pub struct GroupStep<I> {
+    pub frame: Option<Frame>,
+    pub info: Option<I>,
+    // Player's part of the match is over, the stream is closed
+    pub done: bool,
+}
+
+// Step player's match, encode boards shown to the player and get match state
+pub fn group_step<I: GroupInfo>(
+    games: &GroupGames<I::Field>,
+    user_id: u32,
+    encoder: &mut FrameEncoder,
+) -> Option<GroupStep<I>> {
+    games.step(user_id, |game, player| GroupStep {
+        frame: encoder.encode(I::boards(&game.field, player)),
+        info: I::new(&game.field, player, games.tick),
+        done: game.field.is_done(player),
+    })
+}
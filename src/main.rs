@@ -1,48 +1,133 @@
+mod bans;
+mod bot_accounts;
+mod bot_api;
+mod clock;
 mod error;
-mod event_regulator;
-mod matches;
-mod tetris;
-mod tetris_pair;
+mod frames;
+mod group_games;
+mod match_records;
+mod protocol;
+mod rate_limit;
+mod results;
+mod settings;
+mod solo;
 
-use std::sync::{Arc, RwLock};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 
+use bans::Bans;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bot_accounts::BotAccounts;
+use bot_api::{BotCommand, BotMessage, BotStateEncoder};
 use error::Error;
-use matches::{MatchId, Matches, PlayerStatus};
+use frames::{BoardFrame, Frame, FrameEncoder};
+use group_games::{group_step, GroupGames, GroupGuard};
+use match_records::{AbortedGroupMatch, AbortedMatch, MatchRecords, MatchSnapshot};
 use persy::Persy;
+use protocol::{
+    ActionBatch, BattleInfo, ClientMessage, CoopInfo, GroupInfo, MatchEvent, PauseInfo, PhaseInfo,
+    ServerMessage, TeamInfo,
+};
+use rate_limit::RateLimiter;
+use results::SoloResults;
+use rocket::futures::{FutureExt, SinkExt, StreamExt};
 use rocket::tokio::select;
 use rocket::tokio::time::{self, Duration};
 use rocket::{
-    get,
-    http::{Cookie, CookieJar},
+    catch, catchers, get,
+    http::{Cookie, CookieJar, Header, Status},
+    request::{self, FromRequest, Request},
     response::{
         status,
         stream::{Event, EventStream},
     },
     routes,
-    serde::json::serde_json,
+    serde::json::{serde_json, Json},
     Ignite, Rocket, State,
 };
-use rocket::{post, Config, Shutdown};
+use rocket::{post, Config, Responder, Shutdown};
 use rocket_dyn_templates::Template;
-use tetris::Action;
-use tetris_pair::{TetrisPair, TetrisPairState};
+use rocket_ws::{Message, WebSocket};
+use serde::Serialize;
+use settings::Settings;
+use solo::{SoloGame, SoloMode, SoloResult, SoloStatus};
+use tetris_engine::{
+    battle, coop, teams, Action, Battle, Bot, BotDifficulty, CoopMatch, Match, MatchId, Matches,
+    PlayerSide, PlayerStatus, RuleSet, TeamMatch, Tetris, TetrisPair, TetrisPairState,
+};
 
-struct TetrisMatches(Arc<RwLock<Matches<u32, TetrisPair>>>);
+// Player who didn't step the game for this time is considered disconnected
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct TetrisMatches {
+    matches: Arc<RwLock<Matches<u32, TetrisPair>>>,
+    // Computer players by their user ids
+    bots: Mutex<HashMap<u32, Bot>>,
+    // Time when players without match started waiting for opponent
+    waiting_since: Mutex<HashMap<u32, Instant>>,
+    // Wait time before player is matched with a bot, None to never use bots
+    bot_wait: Option<Duration>,
+    bot_difficulty: BotDifficulty,
+    // Wait time before player is removed from the wait list, None to wait forever
+    wait_timeout: Option<Duration>,
+    // Time between game steps
+    tick: Duration,
+    // Rules of matches for players who didn't choose any
+    rules: RuleSet,
+    // Presets players can choose by name, see Settings::presets
+    presets: HashMap<String, RuleSet>,
+    // Rules chosen by players, they are matched only with players who chose the same rules
+    chosen_rules: Mutex<HashMap<u32, RuleSet>>,
+    // Time of the last step by each player, used to detect dropped connections
+    last_seen: Mutex<HashMap<u32, Instant>>,
+    // Time disconnected player can return to the match before it is removed.
+    // The match is paused meanwhile, since boards are stepped only by both players
+    reconnect_grace: Duration,
+    // Time of the last search for abandoned matches
+    last_cleanup: Mutex<Instant>,
+}
 
 impl TetrisMatches {
-    fn new() -> Self {
-        TetrisMatches(Arc::new(RwLock::new(Matches::new())))
+    fn new(settings: &Settings) -> Self {
+        TetrisMatches {
+            matches: Arc::new(RwLock::new(Matches::new())),
+            bots: Mutex::new(HashMap::new()),
+            waiting_since: Mutex::new(HashMap::new()),
+            bot_wait: settings.bot_wait(),
+            bot_difficulty: settings.bot_difficulty,
+            wait_timeout: settings.wait_timeout(),
+            tick: settings.tick(),
+            rules: settings.rules.clone(),
+            presets: settings.presets.clone(),
+            chosen_rules: Mutex::new(HashMap::new()),
+            last_seen: Mutex::new(HashMap::new()),
+            reconnect_grace: settings.reconnect_grace(),
+            last_cleanup: Mutex::new(Instant::now()),
+        }
+    }
+    // Rule set chosen with `rules` query parameter, None for the default rules
+    fn parse_rules(&self, rules: Option<&str>) -> Result<Option<RuleSet>, Status> {
+        rules
+            .map(|name| self.presets.get(name).cloned().ok_or(Status::BadRequest))
+            .transpose()
+    }
+    // Set rules of the next match for player, None for the default rules
+    fn choose_rules(&self, user_id: u32, rules: Option<RuleSet>) {
+        let mut chosen_rules = self.chosen_rules.lock().unwrap();
+        match rules {
+            Some(rules) => chosen_rules.insert(user_id, rules),
+            None => chosen_rules.remove(&user_id),
+        };
     }
     fn get_free_user_id(&self) -> u32 {
-        let mut user_id = rand::random::<u32>();
-        let matches = self.0.read().unwrap();
-        while matches.get_player_status(&user_id) != PlayerStatus::NotFound {
-            user_id = rand::random::<u32>();
-        }
-        user_id
+        free_user_id(&self.matches.read().unwrap())
     }
     fn game_state(&self, user_id: u32) -> Option<TetrisPairState> {
-        let matches = self.0.read().unwrap();
+        let matches = self.matches.read().unwrap();
         matches
             .get_match_for_player(&user_id)
             .and_then(|(_, tetris_match)| {
@@ -50,32 +135,527 @@ impl TetrisMatches {
                 Some(tetris_match.field.get_player_game_state(player_side))
             })
     }
+    // Get result of `f` applied to player's and opponent's boards
+    fn with_player_tetris<R>(
+        &self,
+        user_id: u32,
+        f: impl FnOnce(&Tetris, &Tetris) -> R,
+    ) -> Option<R> {
+        let matches = self.matches.read().unwrap();
+        let (_, tetris_match) = matches.get_match_for_player(&user_id)?;
+        let player_side = tetris_match.get_player_side(&user_id)?;
+        let (player, opponent) = tetris_match.field.get_player_tetris(player_side);
+        Some(f(player, opponent))
+    }
+    // Actions sent while opponent is reconnecting are dropped, so they are not applied at once on return
     fn add_action(&self, user_id: u32, action: Action) {
-        let mut matches = self.0.write().unwrap();
+        let mut matches = self.matches.write().unwrap();
         if let Some((_, tetris_match)) = matches.get_mut_match_for_player(&user_id) {
             if let Some(player_side) = tetris_match.get_player_side(&user_id) {
-                tetris_match.field.add_player_action(player_side, action);
+                let opponent = *tetris_match.get_player(player_side.opponent());
+                if self.is_connected(opponent) {
+                    tetris_match.field.add_player_action(player_side, action);
+                }
             }
         }
     }
-    fn step(&self, user_id: u32) -> Option<TetrisPairState> {
-        let mut matches = self.0.write().unwrap();
-        if matches.find_match(&user_id) {
-            if let Some((match_id, tetris_match)) = matches.get_mut_match_for_player(&user_id) {
-                if let Some(player_side) = tetris_match.get_player_side(&user_id) {
-                    let divergence = tetris_match.field.step_player(player_side);
-                    if divergence < 100 {
-                        return Some(tetris_match.field.get_player_game_state(player_side));
-                    } else {
-                        matches.remove_match(match_id);
-                    }
-                }
+    // Request pause of player's match, bot opponent agrees at once. Returns false if not in a match
+    fn request_pause(&self, user_id: u32) -> bool {
+        let mut matches = self.matches.write().unwrap();
+        let Some((_, tetris_match)) = matches.get_mut_match_for_player(&user_id) else {
+            return false;
+        };
+        let Some(player_side) = tetris_match.get_player_side(&user_id) else {
+            return false;
+        };
+        let opponent_side = player_side.opponent();
+        let opponent = *tetris_match.get_player(opponent_side);
+        tetris_match.field.request_pause(player_side);
+        if self.bots.lock().unwrap().contains_key(&opponent) {
+            tetris_match.field.request_pause(opponent_side);
+        }
+        true
+    }
+    // Player confirms being ready to start the match. Returns false if not in a match
+    fn set_ready(&self, user_id: u32) -> bool {
+        let mut matches = self.matches.write().unwrap();
+        let Some((_, tetris_match)) = matches.get_mut_match_for_player(&user_id) else {
+            return false;
+        };
+        if let Some(player_side) = tetris_match.get_player_side(&user_id) {
+            tetris_match.field.set_ready(player_side);
+        }
+        true
+    }
+    fn resume(&self, user_id: u32) -> bool {
+        let mut matches = self.matches.write().unwrap();
+        matches
+            .get_mut_match_for_player(&user_id)
+            .map(|(_, tetris_match)| tetris_match.field.resume())
+            .is_some()
+    }
+    // Bots are always connected
+    fn is_connected(&self, user_id: u32) -> bool {
+        if self.bots.lock().unwrap().contains_key(&user_id) {
+            return true;
+        }
+        let last_seen = self.last_seen.lock().unwrap();
+        last_seen
+            .get(&user_id)
+            .is_some_and(|seen| seen.elapsed() < DISCONNECT_TIMEOUT)
+    }
+    // Returns false if player's opponent lost connection and the match is paused
+    fn opponent_connected(&self, user_id: u32) -> bool {
+        let matches = self.matches.read().unwrap();
+        let Some((_, tetris_match)) = matches.get_match_for_player(&user_id) else {
+            return true;
+        };
+        tetris_match
+            .get_player_side(&user_id)
+            .is_none_or(|side| self.is_connected(*tetris_match.get_player(side.opponent())))
+    }
+    // Remove matches of players who didn't return within grace period and forget them
+    fn remove_abandoned(&self, matches: &mut Matches<u32, TetrisPair>) {
+        let mut last_seen = self.last_seen.lock().unwrap();
+        let limit = DISCONNECT_TIMEOUT + self.reconnect_grace;
+        let gone: Vec<u32> = last_seen
+            .iter()
+            .filter(|(_, seen)| seen.elapsed() > limit)
+            .map(|(user_id, _)| *user_id)
+            .collect();
+        let abandoned: Vec<MatchId> = gone
+            .iter()
+            .filter_map(|user_id| matches.get_match_for_player(user_id))
+            .map(|(match_id, _)| match_id)
+            .collect();
+        for match_id in abandoned {
+            self.remove_match(matches, match_id);
+        }
+        let mut waiting_since = self.waiting_since.lock().unwrap();
+        let mut chosen_rules = self.chosen_rules.lock().unwrap();
+        for user_id in gone {
+            last_seen.remove(&user_id);
+            waiting_since.remove(&user_id);
+            chosen_rules.remove(&user_id);
+            matches.leave_wait_list(&user_id);
+        }
+    }
+    // Step player's game and get result of `f` applied to the match
+    fn step<R>(
+        &self,
+        user_id: u32,
+        f: impl FnOnce(&Match<u32, TetrisPair>, PlayerSide) -> R,
+    ) -> Option<R> {
+        let mut matches = self.matches.write().unwrap();
+        self.last_seen
+            .lock()
+            .unwrap()
+            .insert(user_id, Instant::now());
+        // Connected players step every tick, so abandoned matches are checked by them once per second
+        let mut last_cleanup = self.last_cleanup.lock().unwrap();
+        if last_cleanup.elapsed() >= DISCONNECT_TIMEOUT {
+            *last_cleanup = Instant::now();
+            self.remove_abandoned(&mut matches);
+        }
+        drop(last_cleanup);
+        let chosen_rules = self.chosen_rules.lock().unwrap();
+        let rules = chosen_rules.get(&user_id).unwrap_or(&self.rules);
+        let same_rules =
+            |other: &u32| chosen_rules.get(other).unwrap_or(&self.rules).name == rules.name;
+        if matches.find_match_with(&user_id, same_rules, || TetrisPair::new(rules)) {
+            self.waiting_since.lock().unwrap().remove(&user_id);
+        } else if !self.match_with_bot(&mut matches, user_id, rules) {
+            return None;
+        }
+        drop(chosen_rules);
+        let (match_id, tetris_match) = matches.get_mut_match_for_player(&user_id)?;
+        let player_side = tetris_match.get_player_side(&user_id)?;
+        // Bot's board is stepped together with it's opponent
+        let opponent_side = player_side.opponent();
+        let opponent = *tetris_match.get_player(opponent_side);
+        if let Some(bot) = self.bots.lock().unwrap().get_mut(&opponent) {
+            let (bot_tetris, _) = tetris_match.field.get_player_tetris(opponent_side);
+            if let Some(action) = bot.think(bot_tetris) {
+                tetris_match.field.add_player_action(opponent_side, action);
+            }
+            tetris_match.field.step_player(opponent_side);
+            tetris_match.field.see_finished(opponent_side);
+        }
+        // Boards are not stepped until disconnected opponent returns, see `remove_abandoned`
+        tetris_match.field.step_player(player_side);
+        let result = f(tetris_match, player_side);
+        // Match is removed when both players have seen it finished or when they didn't pass
+        // the ready check in time. Players return to the wait list on their next step
+        if tetris_match.field.see_finished(player_side)
+            || tetris_match.field.get_lifecycle().is_ready_check_expired()
+        {
+            self.remove_match(&mut matches, match_id);
+        }
+        Some(result)
+    }
+    // Create match with a bot if player is waiting long enough. Returns true if match is created
+    fn match_with_bot(
+        &self,
+        matches: &mut Matches<u32, TetrisPair>,
+        user_id: u32,
+        rules: &RuleSet,
+    ) -> bool {
+        let mut waiting_since = self.waiting_since.lock().unwrap();
+        let since = *waiting_since.entry(user_id).or_insert_with(Instant::now);
+        let Some(bot_wait) = self.bot_wait else {
+            return false;
+        };
+        if since.elapsed() < bot_wait {
+            return false;
+        }
+        waiting_since.remove(&user_id);
+        let bot_id = free_user_id(matches);
+        // Bot is always ready to play
+        let mut field = TetrisPair::new(rules);
+        field.set_ready(PlayerSide::B);
+        matches.create_match_with(user_id, bot_id, field);
+        self.bots
+            .lock()
+            .unwrap()
+            .insert(bot_id, Bot::new(self.bot_difficulty));
+        true
+    }
+    // Remove player from the wait list if waiting longer than wait timeout. Returns true if removed
+    fn wait_timed_out(&self, user_id: u32) -> bool {
+        let Some(wait_timeout) = self.wait_timeout else {
+            return false;
+        };
+        let mut matches = self.matches.write().unwrap();
+        let mut waiting_since = self.waiting_since.lock().unwrap();
+        let timed_out = waiting_since
+            .get(&user_id)
+            .is_some_and(|since| since.elapsed() >= wait_timeout);
+        if timed_out {
+            waiting_since.remove(&user_id);
+            matches.leave_wait_list(&user_id);
+        }
+        timed_out
+    }
+    // Remove match together with it's bots
+    fn remove_match(&self, matches: &mut Matches<u32, TetrisPair>, match_id: MatchId) {
+        if let Some(tetris_match) = matches.get_match(&match_id) {
+            self.remove_bots(tetris_match);
+        }
+        matches.remove_match(match_id);
+    }
+    fn remove_bots(&self, tetris_match: &Match<u32, TetrisPair>) {
+        let mut bots = self.bots.lock().unwrap();
+        bots.remove(&tetris_match.player_a);
+        bots.remove(&tetris_match.player_b);
+    }
+    fn abort_match(&self, match_id: MatchId) -> bool {
+        let mut matches = self.matches.write().unwrap();
+        let found = matches.get_match(&match_id).is_some();
+        self.remove_match(&mut matches, match_id);
+        found
+    }
+    // Remove all matches, returns their records
+    fn abort_all(&self, bot_accounts: &BotAccounts) -> Vec<AbortedMatch> {
+        let mut matches = self.matches.write().unwrap();
+        let match_ids: Vec<MatchId> = matches.iter().map(|(match_id, _)| match_id).collect();
+        let mut aborted = Vec::new();
+        for match_id in match_ids {
+            if let Some(tetris_match) = matches.get_match(&match_id) {
+                let bots = self.bots.lock().unwrap();
+                let is_bot = |user_id| bots.contains_key(&user_id) || bot_accounts.is_bot(user_id);
+                aborted.push(AbortedMatch::new(
+                    tetris_match.player_a,
+                    tetris_match.player_b,
+                    (is_bot(tetris_match.player_a), is_bot(tetris_match.player_b)),
+                    &tetris_match.field,
+                ));
             }
+            self.remove_match(&mut matches, match_id);
+        }
+        aborted
+    }
+    // Remove all matches, returns their snapshots
+    fn snapshot_all(&self) -> Vec<MatchSnapshot> {
+        let mut matches = self.matches.write().unwrap();
+        let mut bots = self.bots.lock().unwrap();
+        let match_ids: Vec<MatchId> = matches.iter().map(|(match_id, _)| match_id).collect();
+        let mut snapshots = Vec::new();
+        for match_id in match_ids {
+            let Some(tetris_match) = matches.remove_match(match_id) else {
+                continue;
+            };
+            let match_bots = [tetris_match.player_a, tetris_match.player_b]
+                .into_iter()
+                .filter_map(|user_id| bots.remove(&user_id).map(|bot| (user_id, bot)))
+                .collect();
+            snapshots.push(MatchSnapshot {
+                player_a: tetris_match.player_a,
+                player_b: tetris_match.player_b,
+                field: tetris_match.field,
+                bots: match_bots,
+            });
+        }
+        snapshots
+    }
+    // Recreate matches from snapshots, players continue them with the same user ids.
+    // Players have reconnect grace period to return
+    fn restore(&self, snapshots: Vec<MatchSnapshot>) {
+        let mut matches = self.matches.write().unwrap();
+        let mut bots = self.bots.lock().unwrap();
+        let mut last_seen = self.last_seen.lock().unwrap();
+        for snapshot in snapshots {
+            last_seen.insert(snapshot.player_a, Instant::now());
+            last_seen.insert(snapshot.player_b, Instant::now());
+            matches.create_match_with(snapshot.player_a, snapshot.player_b, snapshot.field);
+            bots.extend(snapshot.bots);
         }
-        None
     }
+    fn kick(&self, user_id: u32) -> bool {
+        let mut matches = self.matches.write().unwrap();
+        if let Some((_, tetris_match)) = matches.get_match_for_player(&user_id) {
+            self.remove_bots(tetris_match);
+        }
+        matches.kick(&user_id)
+    }
+    // Check and clear player's kick mark. The mark expires with player's reconnect grace, so a
+    // player whose session was already closed when kicked isn't kicked again from a later session
+    fn take_kicked(&self, user_id: u32) -> bool {
+        let kicked = self.matches.write().unwrap().take_kicked(&user_id);
+        let limit = DISCONNECT_TIMEOUT + self.reconnect_grace;
+        kicked
+            && self
+                .last_seen
+                .lock()
+                .unwrap()
+                .get(&user_id)
+                .is_some_and(|seen| seen.elapsed() <= limit)
+    }
+    // Get result of `f` applied to the match without stepping it
+    fn spectate<R>(
+        &self,
+        match_id: MatchId,
+        f: impl FnOnce(&Match<u32, TetrisPair>) -> R,
+    ) -> Option<R> {
+        let matches = self.matches.read().unwrap();
+        matches.get_match(&match_id).map(f)
+    }
+    fn add_spectator(&self, match_id: MatchId) -> bool {
+        let mut matches = self.matches.write().unwrap();
+        if let Some(tetris_match) = matches.get_mut_match(&match_id) {
+            tetris_match.spectators += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn remove_spectator(&self, match_id: MatchId) {
+        let mut matches = self.matches.write().unwrap();
+        if let Some(tetris_match) = matches.get_mut_match(&match_id) {
+            tetris_match.spectators -= 1;
+        }
+    }
+    fn live_matches(&self) -> Vec<LiveMatch> {
+        let matches = self.matches.read().unwrap();
+        matches
+            .iter()
+            .map(|(match_id, tetris_match)| LiveMatch {
+                match_id,
+                spectators: tetris_match.spectators,
+                rules: tetris_match.field.get_rules().name.clone(),
+            })
+            .collect()
+    }
+    fn match_list(&self) -> Vec<MatchInfo> {
+        let matches = self.matches.read().unwrap();
+        matches
+            .iter()
+            .map(|(match_id, tetris_match)| MatchInfo {
+                match_id,
+                player_a: tetris_match.player_a,
+                player_b: tetris_match.player_b,
+                rules: tetris_match.field.get_rules().name.clone(),
+            })
+            .collect()
+    }
+}
+
+// Random user id not used by any player
+fn free_user_id(matches: &Matches<u32, TetrisPair>) -> u32 {
+    let mut user_id = rand::random::<u32>();
+    while matches.get_player_status(&user_id) != PlayerStatus::NotFound {
+        user_id = rand::random::<u32>();
+    }
+    user_id
+}
+
+// Match in public list of live matches
+#[derive(Serialize)]
+struct LiveMatch {
+    match_id: MatchId,
+    spectators: usize,
+    // Name of the rule set
+    rules: String,
+}
+
+// Counts spectator of the match while alive
+struct SpectatorGuard<'a> {
+    matches: &'a TetrisMatches,
+    match_id: MatchId,
+}
+
+impl<'a> SpectatorGuard<'a> {
+    // Returns None if match doesn't exist
+    fn new(matches: &'a TetrisMatches, match_id: MatchId) -> Option<Self> {
+        matches
+            .add_spectator(match_id)
+            .then_some(SpectatorGuard { matches, match_id })
+    }
+}
+
+impl Drop for SpectatorGuard<'_> {
+    fn drop(&mut self) {
+        self.matches.remove_spectator(self.match_id);
+    }
+}
+
+#[derive(Serialize)]
+struct MatchInfo {
+    match_id: MatchId,
+    player_a: u32,
+    player_b: u32,
+    rules: String,
+}
+
+// Single player games, one per user. Game id distinguishes games restarted by the same user
+struct SoloGames {
+    games: RwLock<HashMap<u32, (usize, SoloGame)>>,
+    next_game_id: AtomicUsize,
+    rules: RuleSet,
 }
 
+impl SoloGames {
+    fn new(settings: &Settings) -> Self {
+        SoloGames {
+            games: RwLock::new(HashMap::new()),
+            next_game_id: AtomicUsize::new(0),
+            rules: settings.rules.clone(),
+        }
+    }
+    // Start new game replacing current game of the user. Returns id of the game
+    fn start(&self, user_id: u32, mode: SoloMode, bot: bool) -> usize {
+        let game_id = self.next_game_id.fetch_add(1, Ordering::Relaxed);
+        let mut games = self.games.write().unwrap();
+        games.insert(user_id, (game_id, SoloGame::new(&self.rules, mode, bot)));
+        game_id
+    }
+    // Add action to user's game. Returns false if user doesn't play solo game
+    fn add_action(&self, user_id: u32, action: Action) -> bool {
+        let mut games = self.games.write().unwrap();
+        if let Some((_, game)) = games.get_mut(&user_id) {
+            game.add_action(action);
+            true
+        } else {
+            false
+        }
+    }
+    // Returns false if user doesn't play solo game
+    fn request_pause(&self, user_id: u32) -> bool {
+        let mut games = self.games.write().unwrap();
+        games
+            .get_mut(&user_id)
+            .map(|(_, game)| game.request_pause())
+            .is_some()
+    }
+    fn resume(&self, user_id: u32) -> bool {
+        let mut games = self.games.write().unwrap();
+        games
+            .get_mut(&user_id)
+            .map(|(_, game)| game.resume())
+            .is_some()
+    }
+    // Get result of `f` applied to the game. Returns None if game was replaced by another one
+    fn with_game<R>(
+        &self,
+        user_id: u32,
+        game_id: usize,
+        f: impl FnOnce(&mut SoloGame) -> R,
+    ) -> Option<R> {
+        let mut games = self.games.write().unwrap();
+        games
+            .get_mut(&user_id)
+            .filter(|(id, _)| *id == game_id)
+            .map(|(_, game)| f(game))
+    }
+    // Remove user's game, the stream is closed as if the game was replaced.
+    // Returns false if user doesn't play solo game
+    fn kick(&self, user_id: u32) -> bool {
+        self.games.write().unwrap().remove(&user_id).is_some()
+    }
+    fn remove(&self, user_id: u32, game_id: usize) {
+        let mut games = self.games.write().unwrap();
+        if games.get(&user_id).is_some_and(|(id, _)| *id == game_id) {
+            games.remove(&user_id);
+        }
+    }
+}
+
+// Solo game of the stream, removed when stream is closed
+struct SoloGameGuard<'a> {
+    games: &'a SoloGames,
+    user_id: u32,
+    game_id: usize,
+}
+
+impl<'a> SoloGameGuard<'a> {
+    fn new(games: &'a SoloGames, user_id: u32, mode: SoloMode, bot: bool) -> Self {
+        let game_id = games.start(user_id, mode, bot);
+        SoloGameGuard {
+            games,
+            user_id,
+            game_id,
+        }
+    }
+    // Step the game and encode it's board. Returns None if game was replaced by another one
+    fn step(&self, encoder: &mut FrameEncoder) -> Option<SoloStep> {
+        self.with_game(|game| {
+            let result = game.step().cloned();
+            SoloStep {
+                frame: encoder.encode(vec![BoardFrame::new(game.get_tetris())]),
+                status: game.get_status(),
+                pause: PauseInfo::new(game.get_pause(), PlayerSide::A, solo::STEP),
+                result,
+            }
+        })
+    }
+    fn add_action(&self, action: Action) {
+        self.with_game(|game| game.add_action(action));
+    }
+    // Get result of `f` applied to the game. Returns None if game was replaced by another one
+    fn with_game<R>(&self, f: impl FnOnce(&mut SoloGame) -> R) -> Option<R> {
+        self.games.with_game(self.user_id, self.game_id, f)
+    }
+}
+
+impl Drop for SoloGameGuard<'_> {
+    fn drop(&mut self) {
+        self.games.remove(self.user_id, self.game_id);
+    }
+}
+
+struct SoloStep {
+    frame: Option<Frame>,
+    status: SoloStatus,
+    pause: PauseInfo,
+    result: Option<SoloResult>,
+}
+
+// Battle royale matches
+type Battles = GroupGames<Battle>;
+// 2v2 team matches
+type TeamGames = GroupGames<TeamMatch>;
+// Co-op games on a shared board
+type CoopGames = GroupGames<CoopMatch>;
+
 // Get user id from cookie, if cookie is not set or user id is not valid, create new user id and set cookie
 fn get_or_create_user_id(
     cookie_jar: &CookieJar,
@@ -103,11 +683,89 @@ fn user_id(cookie_jar: &CookieJar, tetris_matches: &TetrisMatches) -> u32 {
     )
 }
 
+// Request guard for player identity. Banned players are rejected with 403
+struct UserId(u32);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UserId {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let rocket = request.rocket();
+        let (Some(tetris_matches), Some(bans)) =
+            (rocket.state::<TetrisMatches>(), rocket.state::<Bans>())
+        else {
+            return request::Outcome::Error((Status::InternalServerError, ()));
+        };
+        let user_id = user_id(request.cookies(), tetris_matches);
+        if bans.is_banned(user_id, request.client_ip()) {
+            request::Outcome::Error((Status::Forbidden, ()))
+        } else {
+            request::Outcome::Success(UserId(user_id))
+        }
+    }
+}
+
+// Request guard for admin pages, checks HTTP basic auth against admin credentials in settings.
+// Admin pages are forbidden if credentials are not set. State-changing admin requests must
+// also carry ADMIN_ACTION_HEADER, cross-site forms can't send it so they are rejected
+struct Admin;
+
+const ADMIN_ACTION_HEADER: &str = "X-Admin-Action";
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let Some(credentials) = request
+            .rocket()
+            .state::<Settings>()
+            .and_then(|settings| settings.admin.as_ref())
+        else {
+            return request::Outcome::Error((Status::Forbidden, ()));
+        };
+        let authorized = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|auth| auth.strip_prefix("Basic "))
+            .and_then(|encoded| BASE64.decode(encoded).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .is_some_and(|decoded| {
+                decoded.split_once(':')
+                    == Some((credentials.user.as_str(), credentials.password.as_str()))
+            });
+        if !authorized {
+            request::Outcome::Error((Status::Unauthorized, ()))
+        } else if request.method() != rocket::http::Method::Get
+            && !request.headers().contains(ADMIN_ACTION_HEADER)
+        {
+            request::Outcome::Error((Status::Forbidden, ()))
+        } else {
+            request::Outcome::Success(Admin)
+        }
+    }
+}
+
+// Asks browser for admin credentials
+#[derive(Responder)]
+#[response(status = 401)]
+struct AdminLogin {
+    message: &'static str,
+    authenticate: Header<'static>,
+}
+
+#[catch(401)]
+fn unauthorized() -> AdminLogin {
+    AdminLogin {
+        message: "Admin credentials required",
+        authenticate: Header::new("WWW-Authenticate", "Basic realm=\"admin\""),
+    }
+}
+
 // Root page handler, returns a string with html content
 #[get("/")]
-fn index(cookie_jar: &CookieJar, tetris_matches: &State<TetrisMatches>) -> String {
-    // Access managed storage with type Tetrises
-    let user_id = user_id(cookie_jar, tetris_matches);
+fn index(user_id: UserId) -> String {
     // tetrises.access_refresh_mut_with_create(&user_id, || Some(Tetris::new(10, 20)), |_| ());
     // let _tetris = tetrises.get_mut_or_else(&user_id, || Tetris::new(10, 20));
     // vec![user_id as usize, users.len()]
@@ -115,17 +773,16 @@ fn index(cookie_jar: &CookieJar, tetris_matches: &State<TetrisMatches>) -> Strin
     //     .map(|v| v.to_string())
     //     .collect::<Vec<_>>()
     //     .join(" ")
-    user_id.to_string()
+    user_id.0.to_string()
 }
 
 // Returns game state as json. Returns HTTP error 404 if user is not found
 #[get("/game_state")]
 fn game_state(
-    cookie_jar: &CookieJar,
+    user_id: UserId,
     matches: &State<TetrisMatches>,
 ) -> Result<String, status::NotFound<String>> {
-    let user_id = user_id(cookie_jar, matches);
-    let game_state = matches.game_state(user_id);
+    let game_state = matches.game_state(user_id.0);
     if let Some(game_state) = game_state {
         Ok(serde_json::to_string(&game_state).unwrap())
     } else {
@@ -135,12 +792,134 @@ fn game_state(
 
 // Admin page, returns a handlebars template
 #[get("/admin")]
-fn admin() -> Template {
+fn admin(_admin: Admin) -> Template {
     let context = ();
     // Render admin/index.html.hbs template
     Template::render("admin/index", &context)
 }
 
+// List of running matches
+#[get("/admin/matches")]
+fn admin_matches(_admin: Admin, matches: &State<TetrisMatches>) -> Json<Vec<MatchInfo>> {
+    Json(matches.match_list())
+}
+
+// Players who sent actions at implausible rate, with number of dropped actions
+#[get("/admin/flagged")]
+fn admin_flagged(_admin: Admin, rate_limiter: &State<RateLimiter<u32>>) -> Json<Vec<(u32, usize)>> {
+    Json(rate_limiter.flagged())
+}
+
+// Matches aborted by server shutdown
+#[get("/admin/aborted")]
+fn admin_aborted(
+    _admin: Admin,
+    match_records: &State<MatchRecords>,
+) -> Result<Json<Vec<AbortedMatch>>, status::Custom<String>> {
+    match_records
+        .get_aborted()
+        .map(Json)
+        .map_err(internal_error)
+}
+
+// Battles, team and co-op matches aborted by server shutdown
+#[get("/admin/aborted/groups")]
+fn admin_aborted_groups(
+    _admin: Admin,
+    match_records: &State<MatchRecords>,
+) -> Result<Json<Vec<AbortedGroupMatch>>, status::Custom<String>> {
+    match_records
+        .get_aborted_groups()
+        .map(Json)
+        .map_err(internal_error)
+}
+
+// Abort running match and remove its bots. Players whose game sessions are still open
+// join the wait list again on their next game step
+#[post("/admin/matches/<match_id>/abort")]
+fn admin_abort_match(
+    _admin: Admin,
+    match_id: MatchId,
+    matches: &State<TetrisMatches>,
+) -> Result<(), status::NotFound<String>> {
+    if matches.abort_match(match_id) {
+        Ok(())
+    } else {
+        Err(status::NotFound("Match not found".to_string()))
+    }
+}
+
+// Remove player from wait lists, lobbies, matches and solo game, close player's game sessions
+#[post("/admin/players/<user_id>/kick")]
+fn admin_kick(
+    _admin: Admin,
+    user_id: u32,
+    matches: &State<TetrisMatches>,
+    solo_games: &State<SoloGames>,
+    battles: &State<Battles>,
+    teams: &State<TeamGames>,
+    coop_games: &State<CoopGames>,
+) -> Result<(), status::NotFound<String>> {
+    // Player may play several games at once, so all of them are checked
+    let kicked = [
+        matches.kick(user_id),
+        solo_games.kick(user_id),
+        battles.kick(user_id),
+        teams.kick(user_id),
+        coop_games.kick(user_id),
+    ];
+    if kicked.contains(&true) {
+        Ok(())
+    } else {
+        Err(status::NotFound("Player not found".to_string()))
+    }
+}
+
+// Ban player identity and kick the player if it's online
+#[post("/admin/players/<user_id>/ban")]
+fn admin_ban_user(
+    _admin: Admin,
+    user_id: u32,
+    matches: &State<TetrisMatches>,
+    bans: &State<Bans>,
+) -> Result<(), status::Custom<String>> {
+    bans.ban_user(user_id).map_err(internal_error)?;
+    matches.kick(user_id);
+    Ok(())
+}
+
+#[post("/admin/players/<user_id>/unban")]
+fn admin_unban_user(
+    _admin: Admin,
+    user_id: u32,
+    bans: &State<Bans>,
+) -> Result<(), status::Custom<String>> {
+    bans.unban_user(user_id).map_err(internal_error)
+}
+
+// Ban ip address. Players from this address are disconnected on next game step
+#[post("/admin/ips/<ip>/ban")]
+fn admin_ban_ip(
+    _admin: Admin,
+    ip: IpAddr,
+    bans: &State<Bans>,
+) -> Result<(), status::Custom<String>> {
+    bans.ban_ip(ip).map_err(internal_error)
+}
+
+#[post("/admin/ips/<ip>/unban")]
+fn admin_unban_ip(
+    _admin: Admin,
+    ip: IpAddr,
+    bans: &State<Bans>,
+) -> Result<(), status::Custom<String>> {
+    bans.unban_ip(ip).map_err(internal_error)
+}
+
+fn internal_error(err: Error) -> status::Custom<String> {
+    status::Custom(Status::InternalServerError, err.to_string())
+}
+
 // Serve specified static file or index.html if only path is given, set rank = 2
 #[get("/<file..>", rank = 2)]
 async fn files(file: std::path::PathBuf) -> Option<rocket::fs::NamedFile> {
@@ -164,125 +943,1169 @@ async fn files(file: std::path::PathBuf) -> Option<rocket::fs::NamedFile> {
     }
 }
 
-// Returns game state as EventStream
-#[get("/sse")]
-fn sse<'a, 'b>(
-    cookie_jar: &'a CookieJar,
-    matches: &'b State<TetrisMatches>,
-) -> EventStream![Event + 'b] {
-    let user_id = user_id(cookie_jar, matches);
-    EventStream! {
-        let mut interval = time::interval(Duration::from_millis(10));
+// Returns game state as EventStream. Optional `rules` parameter selects preset of the match
+#[get("/sse?<rules>")]
+fn sse<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    rules: Option<&str>,
+    matches: &'a State<TetrisMatches>,
+    bans: &'a State<Bans>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![Event + 'a], Status> {
+    let user_id = user_id.0;
+    matches.choose_rules(user_id, matches.parse_rules(rules)?);
+    Ok(EventStream! {
+        let mut interval = time::interval(matches.tick);
+        // SSE client resyncs by reconnecting, so new stream always starts with a keyframe
+        let mut encoder = FrameEncoder::new();
+        // Number of spectators sent to client last time
+        let mut spectators = 0;
+        // Opponent's connection status sent to client last time
+        let mut opponent_connected = true;
+        // Match phase and pause state sent to client last time
+        let mut phase = None;
+        let mut pause = None;
         loop {
-            if let Some(game_state) = matches.step(user_id) {
-                // Send game state as json
-                yield Event::data(serde_json::to_string(&game_state).unwrap());
+            // Kicked or banned player's session is closed. Client should not reconnect
+            if matches.take_kicked(user_id) || bans.is_banned(user_id, ip) {
+                yield Event::data("kicked").event("kicked");
+                break;
+            }
+            // Server is stopping, client may reconnect after restart
+            if (&mut shutdown).now_or_never().is_some() {
+                yield Event::data("shutdown").event("shutdown");
+                break;
+            }
+            let step = matches.step(user_id, |tetris_match, side| {
+                let field = &tetris_match.field;
+                let pause = PauseInfo::new(field.get_pause(), side, matches.tick);
+                let phase = PhaseInfo::new(field.get_lifecycle(), side, matches.tick);
+                (encode_frame(&mut encoder, field, side), tetris_match.spectators, pause, phase)
+            });
+            if let Some((frame, match_spectators, pause_info, phase_info)) = step {
+                // Send game state frame as json, nothing is sent if state is not changed
+                if let Some(frame) = frame {
+                    yield Event::data(serde_json::to_string(&frame).unwrap());
+                }
+                if match_spectators != spectators {
+                    spectators = match_spectators;
+                    yield Event::data(spectators.to_string()).event("spectators");
+                }
+                if phase_info.changed(&phase) {
+                    phase = Some(phase_info);
+                    yield Event::json(&phase_info).event("phase");
+                }
+                if pause_info.changed(&pause) {
+                    pause = Some(pause_info);
+                    yield Event::json(&pause_info).event("pause");
+                }
+                let connected = matches.opponent_connected(user_id);
+                if connected != opponent_connected {
+                    opponent_connected = connected;
+                    let event = if connected { "opponent_returned" } else { "opponent_reconnecting" };
+                    yield Event::data(event).event(event);
+                }
                 interval.tick().await;
+            } else if matches.wait_timed_out(user_id) {
+                yield Event::data("wait_timeout").event("wait_timeout");
+                break;
             } else {
+                // New match starts with a keyframe
+                encoder.resync();
+                spectators = 0;
+                opponent_connected = true;
+                phase = None;
+                pause = None;
                 yield Event::data("foo".to_string());
                 time::sleep(Duration::from_millis(1000)).await;
-                interval = time::interval(Duration::from_millis(10));
+                interval = time::interval(matches.tick);
+            }
+        }
+    })
+}
+
+// Bidirectional game session: receives player's inputs and sends game state and match events.
+// SSE stream and POST routes are kept for clients without WebSocket support
+#[allow(clippy::too_many_arguments)]
+#[get("/ws?<rules>")]
+fn ws<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    rules: Option<&str>,
+    ws: WebSocket,
+    matches: &'a State<TetrisMatches>,
+    bans: &'a State<Bans>,
+    rate_limiter: &'a State<RateLimiter<u32>>,
+    mut shutdown: Shutdown,
+) -> Result<rocket_ws::Channel<'a>, Status> {
+    let user_id = user_id.0;
+    matches.choose_rules(user_id, matches.parse_rules(rules)?);
+    Ok(ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let mut interval = time::interval(matches.tick);
+            // Status sent to client last time, None before first step
+            let mut matched = None;
+            // Sequence number of the last accepted input
+            let mut ack = 0;
+            let mut encoder = FrameEncoder::new();
+            // Number of spectators sent to client last time
+            let mut spectators = 0;
+            // Opponent's connection status sent to client last time
+            let mut opponent_connected = true;
+            // Match phase and pause state sent to client last time
+            let mut phase = None;
+            let mut pause = None;
+            loop {
+                select! {
+                    message = stream.next() => {
+                        let text = match message {
+                            Some(Ok(Message::Text(text))) => text,
+                            Some(Ok(Message::Close(_))) | None => break,
+                            Some(Ok(_)) => continue,
+                            Some(Err(e)) => return Err(e),
+                        };
+                        // Malformed messages are ignored
+                        match serde_json::from_str(&text) {
+                            Ok(ClientMessage::Input { seq, action }) => {
+                                if seq > ack {
+                                    ack = seq;
+                                    if rate_limiter.allow(user_id) {
+                                        matches.add_action(user_id, action);
+                                    }
+                                }
+                            }
+                            Ok(ClientMessage::Resync) => encoder.resync(),
+                            Ok(ClientMessage::Pause) => {
+                                matches.request_pause(user_id);
+                            }
+                            Ok(ClientMessage::Resume) => {
+                                matches.resume(user_id);
+                            }
+                            Ok(ClientMessage::Ready) => {
+                                matches.set_ready(user_id);
+                            }
+                            Ok(ClientMessage::Target { .. }) | Err(_) => {}
+                        }
+                    }
+                    _ = &mut shutdown => {
+                        let event = ServerMessage::MatchEvent { event: MatchEvent::Shutdown };
+                        stream.send(ws_message(&event)).await?;
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        if matches.take_kicked(user_id) || bans.is_banned(user_id, ip) {
+                            let event = ServerMessage::MatchEvent { event: MatchEvent::Kicked };
+                            stream.send(ws_message(&event)).await?;
+                            break;
+                        }
+                        let step = matches.step(user_id, |tetris_match, side| {
+                            let field = &tetris_match.field;
+                            let pause = PauseInfo::new(field.get_pause(), side, matches.tick);
+                            let phase = PhaseInfo::new(field.get_lifecycle(), side, matches.tick);
+                            (encode_frame(&mut encoder, field, side), tetris_match.spectators, pause, phase)
+                        });
+                        let now_matched = step.is_some();
+                        if !now_matched && matches.wait_timed_out(user_id) {
+                            let event = ServerMessage::MatchEvent { event: MatchEvent::WaitTimeout };
+                            stream.send(ws_message(&event)).await?;
+                            break;
+                        }
+                        if matched != Some(now_matched) {
+                            let event = match (matched, now_matched) {
+                                (_, true) => MatchEvent::Matched,
+                                (Some(true), false) => MatchEvent::MatchEnded,
+                                (_, false) => MatchEvent::Waiting,
+                            };
+                            let event = ServerMessage::MatchEvent { event };
+                            stream.send(ws_message(&event)).await?;
+                            // Poll wait list once per second, as SSE stream does
+                            let period = if now_matched { matches.tick } else { Duration::from_secs(1) };
+                            interval = time::interval(period);
+                            interval.tick().await;
+                            matched = Some(now_matched);
+                            if !now_matched {
+                                encoder.resync();
+                                spectators = 0;
+                                opponent_connected = true;
+                                phase = None;
+                                pause = None;
+                            }
+                        }
+                        if let Some((frame, match_spectators, pause_info, phase_info)) = step {
+                            if let Some(frame) = frame {
+                                let frame = ServerMessage::State { ack, frame: &frame };
+                                stream.send(ws_message(&frame)).await?;
+                            }
+                            if match_spectators != spectators {
+                                spectators = match_spectators;
+                                let message = ServerMessage::Spectators { count: spectators };
+                                stream.send(ws_message(&message)).await?;
+                            }
+                            if phase_info.changed(&phase) {
+                                phase = Some(phase_info);
+                                stream.send(ws_message(&ServerMessage::Phase(phase_info))).await?;
+                            }
+                            if pause_info.changed(&pause) {
+                                pause = Some(pause_info);
+                                stream.send(ws_message(&ServerMessage::Pause(pause_info))).await?;
+                            }
+                            let connected = matches.opponent_connected(user_id);
+                            if connected != opponent_connected {
+                                opponent_connected = connected;
+                                let event = if connected {
+                                    MatchEvent::OpponentReturned
+                                } else {
+                                    MatchEvent::OpponentReconnecting
+                                };
+                                stream.send(ws_message(&ServerMessage::MatchEvent { event })).await?;
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    }))
+}
+
+// Public list of live matches
+#[get("/matches")]
+fn live_matches(matches: &State<TetrisMatches>) -> Json<Vec<LiveMatch>> {
+    Json(matches.live_matches())
+}
+
+// Match state stream for spectators. Boards are sent from neutral perspective:
+// player A board first and player B board second
+#[get("/matches/<match_id>/sse")]
+fn spectator_sse(
+    match_id: MatchId,
+    matches: &State<TetrisMatches>,
+    mut shutdown: Shutdown,
+) -> Option<EventStream![Event + '_]> {
+    let guard = SpectatorGuard::new(matches, match_id)?;
+    Some(EventStream! {
+        let _guard = guard;
+        let mut interval = time::interval(matches.tick);
+        let mut encoder = FrameEncoder::new();
+        loop {
+            if (&mut shutdown).now_or_never().is_some() {
+                yield Event::data("shutdown").event("shutdown");
+                break;
+            }
+            let Some(frame) = matches.spectate(match_id, |tetris_match| {
+                encode_frame(&mut encoder, &tetris_match.field, PlayerSide::A)
+            }) else {
+                yield Event::data("match_ended").event("match_ended");
+                break;
+            };
+            if let Some(frame) = frame {
+                yield Event::data(serde_json::to_string(&frame).unwrap());
+            }
+            interval.tick().await;
+        }
+    })
+}
+
+// WebSocket version of spectator stream. Only resync messages are accepted from client
+#[get("/matches/<match_id>/ws")]
+fn spectator_ws(
+    match_id: MatchId,
+    ws: WebSocket,
+    matches: &State<TetrisMatches>,
+    mut shutdown: Shutdown,
+) -> Option<rocket_ws::Channel<'_>> {
+    let guard = SpectatorGuard::new(matches, match_id)?;
+    Some(ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let _guard = guard;
+            let mut interval = time::interval(matches.tick);
+            let mut encoder = FrameEncoder::new();
+            loop {
+                select! {
+                    message = stream.next() => {
+                        match message {
+                            Some(Ok(Message::Text(text))) => {
+                                if let Ok(ClientMessage::Resync) = serde_json::from_str(&text) {
+                                    encoder.resync();
+                                }
+                            }
+                            Some(Ok(Message::Close(_))) | None => break,
+                            Some(Ok(_)) => {}
+                            Some(Err(e)) => return Err(e),
+                        }
+                    }
+                    _ = &mut shutdown => {
+                        let event = ServerMessage::MatchEvent { event: MatchEvent::Shutdown };
+                        stream.send(ws_message(&event)).await?;
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        let frame = matches.spectate(match_id, |tetris_match| {
+                            encode_frame(&mut encoder, &tetris_match.field, PlayerSide::A)
+                        });
+                        match frame {
+                            Some(Some(frame)) => {
+                                let frame = ServerMessage::State { ack: 0, frame: &frame };
+                                stream.send(ws_message(&frame)).await?;
+                            }
+                            Some(None) => {}
+                            None => {
+                                let event = ServerMessage::MatchEvent { event: MatchEvent::MatchEnded };
+                                stream.send(ws_message(&event)).await?;
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    }))
+}
+
+// Stream state of a match of many players. Number of players in the lobby is sent as "lobby"
+// event, match state as I::EVENT event. Stream is closed when player's part of the match is over,
+// client connects again to join the next match
+fn group_sse<'a, I: GroupInfo + 'a>(
+    user_id: u32,
+    ip: Option<IpAddr>,
+    games: &'a GroupGames<I::Field>,
+    bans: &'a Bans,
+    mut shutdown: Shutdown,
+) -> EventStream![Event + 'a]
+where
+    I::Field: Send + Sync,
+{
+    EventStream! {
+        let _guard = GroupGuard { games, user_id, shutdown: shutdown.clone() };
+        let mut interval = time::interval(games.tick);
+        let mut encoder = FrameEncoder::new();
+        // Match state sent to client last time
+        let mut info: Option<I> = None;
+        loop {
+            if games.take_kicked(user_id) || bans.is_banned(user_id, ip) {
+                yield Event::data("kicked").event("kicked");
+                break;
+            }
+            if (&mut shutdown).now_or_never().is_some() {
+                yield Event::data("shutdown").event("shutdown");
+                break;
+            }
+            if let Some(step) = group_step::<I>(games, user_id, &mut encoder) {
+                if let Some(frame) = step.frame {
+                    yield Event::data(serde_json::to_string(&frame).unwrap());
+                }
+                if let Some(group_info) = step.info.filter(|i| i.changed(&info)) {
+                    yield Event::json(&group_info).event(I::EVENT);
+                    info = Some(group_info);
+                }
+                if step.done {
+                    yield Event::data("match_ended").event("match_ended");
+                    break;
+                }
+                interval.tick().await;
+            } else {
+                yield Event::data(games.lobby_len().to_string()).event("lobby");
+                time::sleep(Duration::from_millis(1000)).await;
+                interval = time::interval(games.tick);
+            }
+        }
+    }
+}
+
+// Match of many players over WebSocket, same as group_sse. Accepts inputs and
+// messages specific to the kind of match
+fn group_ws<'a, I: GroupInfo + 'a>(
+    user_id: u32,
+    ip: Option<IpAddr>,
+    ws: WebSocket,
+    games: &'a GroupGames<I::Field>,
+    bans: &'a Bans,
+    rate_limiter: &'a RateLimiter<u32>,
+    mut shutdown: Shutdown,
+) -> rocket_ws::Channel<'a>
+where
+    I::Field: Send + Sync,
+{
+    ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let _guard = GroupGuard { games, user_id, shutdown: shutdown.clone() };
+            let mut interval = time::interval(Duration::from_secs(1));
+            let mut matched = false;
+            let mut ack = 0;
+            let mut encoder = FrameEncoder::new();
+            let mut info: Option<I> = None;
+            loop {
+                select! {
+                    message = stream.next() => {
+                        let text = match message {
+                            Some(Ok(Message::Text(text))) => text,
+                            Some(Ok(Message::Close(_))) | None => break,
+                            Some(Ok(_)) => continue,
+                            Some(Err(e)) => return Err(e),
+                        };
+                        match serde_json::from_str(&text) {
+                            Ok(ClientMessage::Input { seq, action }) => {
+                                if seq > ack {
+                                    ack = seq;
+                                    if rate_limiter.allow(user_id) {
+                                        games.add_action(user_id, action);
+                                    }
+                                }
+                            }
+                            Ok(ClientMessage::Resync) => encoder.resync(),
+                            // These matches can't be paused and start without ready check
+                            Ok(ClientMessage::Pause | ClientMessage::Resume | ClientMessage::Ready) | Err(_) => {}
+                            Ok(message) => {
+                                games.with_field(user_id, |field, player| I::command(field, player, message));
+                            }
+                        }
+                    }
+                    _ = &mut shutdown => {
+                        let event = ServerMessage::MatchEvent { event: MatchEvent::Shutdown };
+                        stream.send(ws_message(&event)).await?;
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        if games.take_kicked(user_id) || bans.is_banned(user_id, ip) {
+                            let event = ServerMessage::MatchEvent { event: MatchEvent::Kicked };
+                            stream.send(ws_message(&event)).await?;
+                            break;
+                        }
+                        let Some(step) = group_step::<I>(games, user_id, &mut encoder) else {
+                            let count = games.lobby_len();
+                            stream.send(ws_message(&ServerMessage::Lobby { count })).await?;
+                            continue;
+                        };
+                        if !matched {
+                            matched = true;
+                            let event = ServerMessage::MatchEvent { event: MatchEvent::Matched };
+                            stream.send(ws_message(&event)).await?;
+                            interval = time::interval(games.tick);
+                            interval.tick().await;
+                        }
+                        if let Some(frame) = step.frame {
+                            let frame = ServerMessage::State { ack, frame: &frame };
+                            stream.send(ws_message(&frame)).await?;
+                        }
+                        if let Some(group_info) = step.info.filter(|i| i.changed(&info)) {
+                            info = Some(group_info.clone());
+                            stream.send(ws_message(&group_info.message())).await?;
+                        }
+                        if step.done {
+                            let event = ServerMessage::MatchEvent { event: MatchEvent::MatchEnded };
+                            stream.send(ws_message(&event)).await?;
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    })
+}
+
+// Join battle royale and stream it's state, battle state is sent as "battle" event.
+// Stream is closed when player is knocked out or wins
+#[get("/battle/sse")]
+fn battle_sse<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    battles: &'a State<Battles>,
+    bans: &'a State<Bans>,
+    shutdown: Shutdown,
+) -> EventStream![Event + 'a] {
+    group_sse::<BattleInfo>(user_id.0, ip, battles, bans, shutdown)
+}
+
+// Battle royale over WebSocket, same as battle_sse. Accepts inputs and targeting choice
+#[get("/battle/ws")]
+fn battle_ws<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    ws: WebSocket,
+    battles: &'a State<Battles>,
+    bans: &'a State<Bans>,
+    rate_limiter: &'a State<RateLimiter<u32>>,
+    shutdown: Shutdown,
+) -> rocket_ws::Channel<'a> {
+    group_ws::<BattleInfo>(user_id.0, ip, ws, battles, bans, rate_limiter, shutdown)
+}
+
+// Targeting for clients without WebSocket: random, attackers, kos or badges
+#[post("/battle/target/<targeting>")]
+fn battle_target(user_id: UserId, targeting: &str, battles: &State<Battles>) -> Result<(), Status> {
+    let targeting = targeting.parse().map_err(|_| Status::BadRequest)?;
+    if battles.with_field(user_id.0, |battle, player| {
+        battle.set_targeting(player, targeting)
+    }) {
+        Ok(())
+    } else {
+        Err(Status::NotFound)
+    }
+}
+
+// Join 2v2 team match and stream it's state, match state is sent as "team" event.
+// Stream is closed when the match is finished
+#[get("/teams/sse")]
+fn teams_sse<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    teams: &'a State<TeamGames>,
+    bans: &'a State<Bans>,
+    shutdown: Shutdown,
+) -> EventStream![Event + 'a] {
+    group_sse::<TeamInfo>(user_id.0, ip, teams, bans, shutdown)
+}
+
+// Team match over WebSocket, same as teams_sse
+#[get("/teams/ws")]
+fn teams_ws<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    ws: WebSocket,
+    teams: &'a State<TeamGames>,
+    bans: &'a State<Bans>,
+    rate_limiter: &'a State<RateLimiter<u32>>,
+    shutdown: Shutdown,
+) -> rocket_ws::Channel<'a> {
+    group_ws::<TeamInfo>(user_id.0, ip, ws, teams, bans, rate_limiter, shutdown)
+}
+
+// Join co-op game on a shared board and stream it's state, game state is sent as "coop" event.
+// Stream is closed when the game is over
+#[get("/coop/sse")]
+fn coop_sse<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    coop_games: &'a State<CoopGames>,
+    bans: &'a State<Bans>,
+    shutdown: Shutdown,
+) -> EventStream![Event + 'a] {
+    group_sse::<CoopInfo>(user_id.0, ip, coop_games, bans, shutdown)
+}
+
+// Co-op game over WebSocket, same as coop_sse
+#[get("/coop/ws")]
+fn coop_ws<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    ws: WebSocket,
+    coop_games: &'a State<CoopGames>,
+    bans: &'a State<Bans>,
+    rate_limiter: &'a State<RateLimiter<u32>>,
+    shutdown: Shutdown,
+) -> rocket_ws::Channel<'a> {
+    group_ws::<CoopInfo>(user_id.0, ip, ws, coop_games, bans, rate_limiter, shutdown)
+}
+
+// Start single player game and stream it's state. Game status is sent as "status" event,
+// result of finished game as "finished" event, after which the stream is closed
+#[allow(clippy::too_many_arguments)]
+#[get("/solo/<mode>/sse")]
+fn solo_sse<'a>(
+    mode: &str,
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    solo_games: &'a State<SoloGames>,
+    solo_results: &'a State<SoloResults>,
+    bans: &'a State<Bans>,
+    bot_accounts: &State<BotAccounts>,
+    mut shutdown: Shutdown,
+) -> Option<EventStream![Event + 'a]> {
+    let mode = mode.parse().ok()?;
+    let user_id = user_id.0;
+    let game = SoloGameGuard::new(solo_games, user_id, mode, bot_accounts.is_bot(user_id));
+    Some(EventStream! {
+        let mut interval = time::interval(solo::STEP);
+        let mut encoder = FrameEncoder::new();
+        let mut status = None;
+        let mut pause = None;
+        loop {
+            // Stop if game was replaced by another one, client should not reconnect
+            let Some(step) = game.step(&mut encoder).filter(|_| !bans.is_banned(user_id, ip)) else {
+                yield Event::data("kicked").event("kicked");
+                break;
+            };
+            // Server is stopping, client may reconnect after restart
+            if (&mut shutdown).now_or_never().is_some() {
+                yield Event::data("shutdown").event("shutdown");
+                break;
             }
+            if let Some(frame) = step.frame {
+                yield Event::data(serde_json::to_string(&frame).unwrap());
+            }
+            if status != Some(step.status) {
+                status = Some(step.status);
+                yield Event::json(&step.status).event("status");
+            }
+            if step.pause.changed(&pause) {
+                pause = Some(step.pause);
+                yield Event::json(&step.pause).event("pause");
+            }
+            if let Some(result) = step.result {
+                record_solo_result(solo_results, user_id, &result);
+                yield Event::json(&result).event("finished");
+                break;
+            }
+            interval.tick().await;
         }
+    })
+}
+
+// WebSocket version of single player game
+#[allow(clippy::too_many_arguments)]
+#[get("/solo/<mode>/ws")]
+fn solo_ws<'a>(
+    mode: &str,
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    ws: WebSocket,
+    solo_games: &'a State<SoloGames>,
+    solo_results: &'a State<SoloResults>,
+    bans: &'a State<Bans>,
+    rate_limiter: &'a State<RateLimiter<u32>>,
+    bot_accounts: &State<BotAccounts>,
+    mut shutdown: Shutdown,
+) -> Option<rocket_ws::Channel<'a>> {
+    let mode = mode.parse().ok()?;
+    let user_id = user_id.0;
+    let game = SoloGameGuard::new(solo_games, user_id, mode, bot_accounts.is_bot(user_id));
+    Some(ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let mut interval = time::interval(solo::STEP);
+            let mut encoder = FrameEncoder::new();
+            let mut status = None;
+            let mut pause = None;
+            let mut ack = 0;
+            loop {
+                select! {
+                    message = stream.next() => {
+                        let text = match message {
+                            Some(Ok(Message::Text(text))) => text,
+                            Some(Ok(Message::Close(_))) | None => break,
+                            Some(Ok(_)) => continue,
+                            Some(Err(e)) => return Err(e),
+                        };
+                        match serde_json::from_str(&text) {
+                            Ok(ClientMessage::Input { seq, action }) => {
+                                if seq > ack {
+                                    ack = seq;
+                                    if rate_limiter.allow(user_id) {
+                                        game.add_action(action);
+                                    }
+                                }
+                            }
+                            Ok(ClientMessage::Resync) => encoder.resync(),
+                            Ok(ClientMessage::Pause) => {
+                                game.with_game(|game| game.request_pause());
+                            }
+                            Ok(ClientMessage::Resume) => {
+                                game.with_game(|game| game.resume());
+                            }
+                            // Single player game starts at once and has no opponents
+                            Ok(ClientMessage::Ready | ClientMessage::Target { .. }) | Err(_) => {}
+                        }
+                    }
+                    _ = &mut shutdown => {
+                        let event = ServerMessage::MatchEvent { event: MatchEvent::Shutdown };
+                        stream.send(ws_message(&event)).await?;
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        let Some(step) = game.step(&mut encoder).filter(|_| !bans.is_banned(user_id, ip)) else {
+                            let event = ServerMessage::MatchEvent { event: MatchEvent::Kicked };
+                            stream.send(ws_message(&event)).await?;
+                            break;
+                        };
+                        if let Some(frame) = step.frame {
+                            stream.send(ws_message(&ServerMessage::State { ack, frame: &frame })).await?;
+                        }
+                        if status != Some(step.status) {
+                            status = Some(step.status);
+                            stream.send(ws_message(&ServerMessage::SoloStatus { status: &step.status })).await?;
+                        }
+                        if step.pause.changed(&pause) {
+                            pause = Some(step.pause);
+                            stream.send(ws_message(&ServerMessage::Pause(step.pause))).await?;
+                        }
+                        if let Some(result) = step.result {
+                            record_solo_result(solo_results, user_id, &result);
+                            stream.send(ws_message(&ServerMessage::SoloFinished { result: &result })).await?;
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    }))
+}
+
+// Results of user's finished single player games
+#[get("/solo/results")]
+fn solo_results(
+    user_id: UserId,
+    solo_results: &State<SoloResults>,
+) -> Result<Json<Vec<SoloResult>>, status::Custom<String>> {
+    solo_results
+        .get_user_results(user_id.0)
+        .map(Json)
+        .map_err(internal_error)
+}
+
+// Register user as external AI player. Results of bot accounts are flagged
+#[post("/bot/register")]
+fn bot_register(
+    user_id: UserId,
+    bot_accounts: &State<BotAccounts>,
+) -> Result<Json<u32>, status::Custom<String>> {
+    bot_accounts
+        .register(user_id.0)
+        .map(|_| Json(user_id.0))
+        .map_err(internal_error)
+}
+
+// Match for bot accounts, see bot_api module for the protocol
+#[allow(clippy::too_many_arguments)]
+#[get("/bot/ws?<rules>")]
+fn bot_ws<'a>(
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    rules: Option<&str>,
+    ws: WebSocket,
+    matches: &'a State<TetrisMatches>,
+    bans: &'a State<Bans>,
+    rate_limiter: &'a State<RateLimiter<u32>>,
+    bot_accounts: &State<BotAccounts>,
+    mut shutdown: Shutdown,
+) -> Result<rocket_ws::Channel<'a>, Status> {
+    let user_id = user_id.0;
+    if !bot_accounts.is_bot(user_id) {
+        return Err(Status::Forbidden);
     }
+    matches.choose_rules(user_id, matches.parse_rules(rules)?);
+    Ok(ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let mut interval = time::interval(matches.tick);
+            let mut matched = None;
+            let mut encoder = BotStateEncoder::new();
+            let mut opponent_connected = true;
+            loop {
+                select! {
+                    message = stream.next() => {
+                        let text = match message {
+                            Some(Ok(Message::Text(text))) => text,
+                            Some(Ok(Message::Close(_))) | None => break,
+                            Some(Ok(_)) => continue,
+                            Some(Err(e)) => return Err(e),
+                        };
+                        let actions = serde_json::from_str::<BotCommand>(&text)
+                            .map_err(|e| e.to_string())
+                            .and_then(|command| {
+                                matches
+                                    .with_player_tetris(user_id, |player, _| command.actions(player))
+                                    .unwrap_or_else(|| Err("Not in a match".to_string()))
+                            });
+                        match actions {
+                            // Whole command is dropped if it's above the rate limit
+                            Ok(actions) if rate_limiter.allow(user_id) => {
+                                for action in actions {
+                                    matches.add_action(user_id, action);
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(message) => stream.send(bot_message(&BotMessage::Error { message })).await?,
+                        }
+                    }
+                    _ = &mut shutdown => {
+                        let event = BotMessage::MatchEvent { event: MatchEvent::Shutdown };
+                        stream.send(bot_message(&event)).await?;
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        if matches.take_kicked(user_id) || bans.is_banned(user_id, ip) {
+                            let event = BotMessage::MatchEvent { event: MatchEvent::Kicked };
+                            stream.send(bot_message(&event)).await?;
+                            break;
+                        }
+                        let step = matches.step(user_id, |tetris_match, side| {
+                            let (player, opponent) = tetris_match.field.get_player_tetris(side);
+                            encoder.encode(player, Some(opponent))
+                        });
+                        let now_matched = step.is_some();
+                        if !now_matched && matches.wait_timed_out(user_id) {
+                            let event = BotMessage::MatchEvent { event: MatchEvent::WaitTimeout };
+                            stream.send(bot_message(&event)).await?;
+                            break;
+                        }
+                        if matched != Some(now_matched) {
+                            let event = match (matched, now_matched) {
+                                (_, true) => MatchEvent::Matched,
+                                (Some(true), false) => MatchEvent::MatchEnded,
+                                (_, false) => MatchEvent::Waiting,
+                            };
+                            stream.send(bot_message(&BotMessage::MatchEvent { event })).await?;
+                            // Bots skip the ready check, match starts after countdown
+                            if now_matched {
+                                matches.set_ready(user_id);
+                            }
+                            // Poll wait list once per second, as player's stream does
+                            let period = if now_matched { matches.tick } else { Duration::from_secs(1) };
+                            interval = time::interval(period);
+                            interval.tick().await;
+                            matched = Some(now_matched);
+                            if !now_matched {
+                                encoder.reset();
+                                opponent_connected = true;
+                            }
+                        }
+                        if let Some(Some(state)) = step {
+                            stream.send(Message::Text(state)).await?;
+                        }
+                        let connected = !now_matched || matches.opponent_connected(user_id);
+                        if connected != opponent_connected {
+                            opponent_connected = connected;
+                            let event = if connected {
+                                MatchEvent::OpponentReturned
+                            } else {
+                                MatchEvent::OpponentReconnecting
+                            };
+                            stream.send(bot_message(&BotMessage::MatchEvent { event })).await?;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    }))
 }
 
-// When /down url is requested, move tetris figure down
-#[post("/down")]
-fn down(cookie_jar: &CookieJar, matches: &State<TetrisMatches>) {
-    let user_id = user_id(cookie_jar, matches);
-    matches.add_action(user_id, Action::MoveDown);
+// Single player game for bot accounts, see bot_api module for the protocol
+#[allow(clippy::too_many_arguments)]
+#[get("/bot/solo/<mode>/ws")]
+fn bot_solo_ws<'a>(
+    mode: &str,
+    user_id: UserId,
+    ip: Option<IpAddr>,
+    ws: WebSocket,
+    solo_games: &'a State<SoloGames>,
+    solo_results: &'a State<SoloResults>,
+    bans: &'a State<Bans>,
+    rate_limiter: &'a State<RateLimiter<u32>>,
+    bot_accounts: &State<BotAccounts>,
+    mut shutdown: Shutdown,
+) -> Result<rocket_ws::Channel<'a>, Status> {
+    let mode = mode.parse().map_err(|_| Status::NotFound)?;
+    let user_id = user_id.0;
+    if !bot_accounts.is_bot(user_id) {
+        return Err(Status::Forbidden);
+    }
+    let game = SoloGameGuard::new(solo_games, user_id, mode, true);
+    Ok(ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let mut interval = time::interval(solo::STEP);
+            let mut encoder = BotStateEncoder::new();
+            loop {
+                select! {
+                    message = stream.next() => {
+                        let text = match message {
+                            Some(Ok(Message::Text(text))) => text,
+                            Some(Ok(Message::Close(_))) | None => break,
+                            Some(Ok(_)) => continue,
+                            Some(Err(e)) => return Err(e),
+                        };
+                        let actions = serde_json::from_str::<BotCommand>(&text)
+                            .map_err(|e| e.to_string())
+                            .and_then(|command| {
+                                game.with_game(|game| command.actions(game.get_tetris()))
+                                    .unwrap_or_else(|| Err("Game is replaced".to_string()))
+                            });
+                        match actions {
+                            Ok(actions) if rate_limiter.allow(user_id) => {
+                                for action in actions {
+                                    game.add_action(action);
+                                }
+                            }
+                            Ok(_) => {}
+                            Err(message) => stream.send(bot_message(&BotMessage::Error { message })).await?,
+                        }
+                    }
+                    _ = &mut shutdown => {
+                        let event = BotMessage::MatchEvent { event: MatchEvent::Shutdown };
+                        stream.send(bot_message(&event)).await?;
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        let step = game.with_game(|game| {
+                            let result = game.step().cloned();
+                            (encoder.encode(game.get_tetris(), None), result)
+                        });
+                        let Some((state, result)) = step.filter(|_| !bans.is_banned(user_id, ip)) else {
+                            let event = BotMessage::MatchEvent { event: MatchEvent::Kicked };
+                            stream.send(bot_message(&event)).await?;
+                            break;
+                        };
+                        if let Some(state) = state {
+                            stream.send(Message::Text(state)).await?;
+                        }
+                        if let Some(result) = result {
+                            record_solo_result(solo_results, user_id, &result);
+                            stream.send(bot_message(&BotMessage::SoloFinished { result: &result })).await?;
+                            break;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        })
+    }))
+}
+
+// Failure to save result should not break the game stream, so it's only logged
+fn record_solo_result(solo_results: &SoloResults, user_id: u32, result: &SoloResult) {
+    if let Err(e) = solo_results.record(user_id, result) {
+        println!("Failed to record solo game result: {}", e);
+    }
+}
+
+fn encode_frame(encoder: &mut FrameEncoder, pair: &TetrisPair, side: PlayerSide) -> Option<Frame> {
+    let (player, opponent) = pair.get_player_tetris(side);
+    encoder.encode(vec![BoardFrame::new(player), BoardFrame::new(opponent)])
 }
 
-// When /left url is requested, move tetris figure left
-#[post("/left")]
-fn left(cookie_jar: &CookieJar, matches: &State<TetrisMatches>) {
-    let user_id = user_id(cookie_jar, matches);
-    matches.add_action(user_id, Action::MoveLeft);
+fn ws_message(message: &ServerMessage) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap())
 }
 
-// When /right url is requested, move tetris figure right
-#[post("/right")]
-fn right(cookie_jar: &CookieJar, matches: &State<TetrisMatches>) {
-    let user_id = user_id(cookie_jar, matches);
-    matches.add_action(user_id, Action::MoveRight);
+fn bot_message(message: &BotMessage) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap())
 }
 
-// When /rotate_right url is requested, rotate tetris figure right
-#[post("/rotate_right")]
-fn rotate_right(cookie_jar: &CookieJar, matches: &State<TetrisMatches>) {
-    let user_id = user_id(cookie_jar, matches);
-    matches.add_action(user_id, Action::RotateRight);
+// Add player's actions. Actions are queued in order of client timestamps, untimed actions
+// keep their place in the body.
+// Only player actions can be deserialized, so system actions like BottomRefill are rejected.
+// Actions above the rate limit are dropped
+#[post("/action", data = "<batch>")]
+#[allow(clippy::too_many_arguments)]
+fn action(
+    user_id: UserId,
+    batch: ActionBatch,
+    matches: &State<TetrisMatches>,
+    solo_games: &State<SoloGames>,
+    battles: &State<Battles>,
+    teams: &State<TeamGames>,
+    coop_games: &State<CoopGames>,
+    rate_limiter: &State<RateLimiter<u32>>,
+) {
+    for action in batch.into_ordered() {
+        // Solo game takes precedence over games of many players, they go before the duel
+        if rate_limiter.allow(user_id.0)
+            && !solo_games.add_action(user_id.0, action)
+            && !battles.add_action(user_id.0, action)
+            && !teams.add_action(user_id.0, action)
+            && !coop_games.add_action(user_id.0, action)
+        {
+            matches.add_action(user_id.0, action);
+        }
+    }
 }
 
-// When /rotate_left url is requested, rotate tetris figure left
-#[post("/rotate_left")]
-fn rotate_left(cookie_jar: &CookieJar, matches: &State<TetrisMatches>) {
-    let user_id = user_id(cookie_jar, matches);
-    matches.add_action(user_id, Action::RotateLeft);
+// Pause for clients without WebSocket. Solo game takes precedence over the match
+#[post("/pause")]
+fn pause(
+    user_id: UserId,
+    matches: &State<TetrisMatches>,
+    solo_games: &State<SoloGames>,
+) -> Result<(), status::NotFound<String>> {
+    if solo_games.request_pause(user_id.0) || matches.request_pause(user_id.0) {
+        Ok(())
+    } else {
+        Err(status::NotFound("Game not found".to_string()))
+    }
 }
 
-// When /drop url is requested, drop tetris figure
-#[post("/drop")]
-fn drop(cookie_jar: &CookieJar, matches: &State<TetrisMatches>) {
-    let user_id = user_id(cookie_jar, matches);
-    matches.add_action(user_id, Action::Drop);
+#[post("/resume")]
+fn resume(
+    user_id: UserId,
+    matches: &State<TetrisMatches>,
+    solo_games: &State<SoloGames>,
+) -> Result<(), status::NotFound<String>> {
+    if solo_games.resume(user_id.0) || matches.resume(user_id.0) {
+        Ok(())
+    } else {
+        Err(status::NotFound("Game not found".to_string()))
+    }
 }
 
-#[post("/bottom_refill")]
-fn bottom_refill(cookie_jar: &CookieJar, matches: &State<TetrisMatches>) {
-    let user_id = user_id(cookie_jar, matches);
-    matches.add_action(user_id, Action::BottomRefill);
+// Ready confirmation for clients without WebSocket
+#[post("/ready")]
+fn ready(user_id: UserId, matches: &State<TetrisMatches>) -> Result<(), status::NotFound<String>> {
+    if matches.set_ready(user_id.0) {
+        Ok(())
+    } else {
+        Err(status::NotFound("Match not found".to_string()))
+    }
 }
 
 // .ok_or(status::NotFound("User not found".to_string()));
 async fn init() -> Result<Rocket<Ignite>, Error> {
-    // Get executable name without extension
-    let exe_name = std::env::current_exe()?;
-    // Remove extension
-    let db_name = exe_name
-        .file_stem()
-        .map(|s| s.to_str())
-        .flatten()
-        .unwrap_or("gameserver");
-    // make db name = executable name + ".db"
-    let db_name = db_name.to_owned() + ".db";
+    // Read settings from Rocket.toml, environment variables override them
+    let mut settings: Settings = Config::figment().extract()?;
+    settings
+        .apply_rules()
+        .map_err(rocket::figment::Error::from)?;
     // create or open Persy database storage
-    println!("Database file: {}", db_name);
+    println!("Database file: {}", settings.database.display());
     let config = persy::Config::default();
-    Persy::open_or_create_with(db_name, config, |_persy| Ok(()))?;
+    let persy = Persy::open_or_create_with(&settings.database, config, |_persy| Ok(()))?;
+    let bans = Bans::open(persy.clone())?;
+    let bot_accounts = BotAccounts::open(persy.clone())?;
+    let match_records = MatchRecords::open(persy.clone())?;
+    let solo_results = SoloResults::open(persy)?;
+    if settings.admin.is_none() {
+        println!("Admin credentials are not set, admin pages are disabled");
+    }
+
+    // Create matches storage, matches saved on last shutdown are resumed
+    let matches = TetrisMatches::new(&settings);
+    if settings.resume_matches {
+        let snapshots = match_records.take_snapshots()?;
+        println!("Resumed {} matches", snapshots.len());
+        matches.restore(snapshots);
+    }
 
-    // Create matches storage
-    let matches = TetrisMatches::new();
+    let solo_games = SoloGames::new(&settings);
+    let battles = Battles::new(
+        &settings,
+        settings.battle_min_players.max(2),
+        settings.battle_max_players.clamp(2, battle::MAX_PLAYERS),
+        settings.battle_wait(),
+        Battle::new,
+    );
+    // Team match starts as soon as both teams are full
+    let team_size = teams::TEAM_SIZE * teams::TEAMS;
+    let team_games = TeamGames::new(
+        &settings,
+        team_size,
+        team_size,
+        Duration::ZERO,
+        |rules, _| TeamMatch::new(rules),
+    );
+    let coop_games = CoopGames::new(
+        &settings,
+        coop::COOP_PLAYERS,
+        coop::COOP_PLAYERS,
+        Duration::ZERO,
+        CoopMatch::new,
+    );
 
     // Start rocket server
     let rocket = rocket::build()
-        // Read config from Rocket.toml
-        .manage(Config::figment())
+        // Settings read from Rocket.toml
+        .manage(settings)
         // Attach Template::fairing() to rocket instance
         .attach(Template::fairing())
         // Matches
         .manage(matches)
+        // Banned players and ip addresses
+        .manage(bans)
+        // Single player games and their results
+        .manage(solo_games)
+        // Battle royale
+        .manage(battles)
+        // Team matches
+        .manage(team_games)
+        // Co-op games
+        .manage(coop_games)
+        .manage(solo_results)
+        // Matches aborted by shutdown
+        .manage(match_records)
+        // External AI players
+        .manage(bot_accounts)
+        // Players' actions rate limits
+        .manage(RateLimiter::<u32>::new())
         // Mount index route
         .mount("/", routes![index, admin, files, game_state])
+        // Moderation
         .mount(
             "/",
             routes![
-                sse,
-                down,
-                left,
-                right,
-                rotate_right,
-                rotate_left,
-                drop,
-                bottom_refill
+                admin_matches,
+                admin_flagged,
+                admin_aborted,
+                admin_aborted_groups,
+                admin_abort_match,
+                admin_kick,
+                admin_ban_user,
+                admin_unban_user,
+                admin_ban_ip,
+                admin_unban_ip
             ],
         )
+        .mount("/", routes![sse, ws, action, pause, resume, ready])
+        // Spectators
+        .mount("/", routes![live_matches, spectator_sse, spectator_ws])
+        // Single player
+        .mount("/", routes![solo_sse, solo_ws, solo_results])
+        // Battle royale
+        .mount("/", routes![battle_sse, battle_ws, battle_target])
+        // Team matches
+        .mount("/", routes![teams_sse, teams_ws])
+        // Co-op games
+        .mount("/", routes![coop_sse, coop_ws])
+        // Bot API
+        .mount("/", routes![bot_register, bot_ws, bot_solo_ws])
+        // Browser asks for admin credentials
+        .register("/", catchers![unauthorized])
         .launch()
         .await?;
+    // Streams are closed at this point, so matches still running can be safely saved
+    save_matches(&rocket);
     Ok(rocket)
 }
 
+// Save snapshots of running matches to resume them after restart or record them as aborted
+fn save_matches(rocket: &Rocket<Ignite>) {
+    let (Some(settings), Some(matches), Some(match_records), Some(bot_accounts)) = (
+        rocket.state::<Settings>(),
+        rocket.state::<TetrisMatches>(),
+        rocket.state::<MatchRecords>(),
+        rocket.state::<BotAccounts>(),
+    ) else {
+        return;
+    };
+    if settings.resume_matches {
+        let snapshots = matches.snapshot_all();
+        match match_records.save_snapshots(&snapshots) {
+            Ok(()) => println!("Saved {} matches to resume", snapshots.len()),
+            Err(e) => println!("Failed to save matches: {}", e),
+        }
+    } else {
+        let aborted = matches.abort_all(bot_accounts);
+        match match_records.record_aborted(&aborted) {
+            Ok(()) => println!("Recorded {} aborted matches", aborted.len()),
+            Err(e) => println!("Failed to record aborted matches: {}", e),
+        }
+    }
+    // Matches of many players can't be resumed, they are always recorded as aborted
+    let mut aborted = Vec::new();
+    if let Some(battles) = rocket.state::<Battles>() {
+        aborted.extend(battles.abort_all("battle", bot_accounts));
+    }
+    if let Some(team_games) = rocket.state::<TeamGames>() {
+        aborted.extend(team_games.abort_all("teams", bot_accounts));
+    }
+    if let Some(coop_games) = rocket.state::<CoopGames>() {
+        aborted.extend(coop_games.abort_all("coop", bot_accounts));
+    }
+    match match_records.record_aborted_groups(&aborted) {
+        Ok(()) => println!("Recorded {} aborted group matches", aborted.len()),
+        Err(e) => println!("Failed to record aborted group matches: {}", e),
+    }
+}
+
 #[rocket::main]
 async fn main() {
     // Handle result
@@ -0,0 +1,202 @@
+use persy::{Persy, PersyId};
+use rocket::serde::json::serde_json;
+use serde::{Deserialize, Serialize};
+use tetris_engine::{Bot, GroupField, PlayerSide, TetrisPair};
+
+use crate::clock::now;
+use crate::error::Error;
+
+// Persy segment with matches aborted by server shutdown as json records
+const ABORTED_MATCHES_SEGMENT: &str = "aborted_matches";
+// Persy segment with matches of many players aborted by server shutdown
+const ABORTED_GROUP_MATCHES_SEGMENT: &str = "aborted_group_matches";
+// Persy segment with snapshots of matches to resume after restart
+const MATCH_SNAPSHOTS_SEGMENT: &str = "match_snapshots";
+
+// Match which was still running when server was stopped
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AbortedMatch {
+    pub player_a: u32,
+    pub player_b: u32,
+    // Name of the rule set
+    pub rules: String,
+    pub score_a: usize,
+    pub score_b: usize,
+    pub lines_a: usize,
+    pub lines_b: usize,
+    // Player is a bot account or a computer player. Absent in records made before bot accounts
+    // were added
+    #[serde(default)]
+    pub bot_a: bool,
+    #[serde(default)]
+    pub bot_b: bool,
+    // Abort time in seconds since epoch
+    pub time: u64,
+}
+
+impl AbortedMatch {
+    pub fn new(player_a: u32, player_b: u32, bots: (bool, bool), pair: &TetrisPair) -> Self {
+        let (tetris_a, tetris_b) = pair.get_player_tetris(PlayerSide::A);
+        AbortedMatch {
+            player_a,
+            player_b,
+            rules: pair.get_rules().name.clone(),
+            score_a: tetris_a.get_score(),
+            score_b: tetris_b.get_score(),
+            lines_a: tetris_a.get_lines(),
+            lines_b: tetris_b.get_lines(),
+            bot_a: bots.0,
+            bot_b: bots.1,
+            time: now(),
+        }
+    }
+}
+
+// Battle, team or co-op match which was still running when server was stopped.
+// Matches of many players are not resumed after restart
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AbortedGroupMatch {
+    // Kind of the match: battle, teams or coop
+    pub kind: String,
+    pub players: Vec<u32>,
+    // Name of the rule set
+    pub rules: String,
+    // Score and lines of each player in order of `players`
+    pub scores: Vec<usize>,
+    pub lines: Vec<usize>,
+    // Player is a bot account, in order of `players`. Empty in records made before bot accounts
+    // were added
+    #[serde(default)]
+    pub bots: Vec<bool>,
+    // Abort time in seconds since epoch
+    pub time: u64,
+}
+
+impl AbortedGroupMatch {
+    pub fn new(
+        kind: &str,
+        rules: &str,
+        players: Vec<u32>,
+        is_bot: impl Fn(u32) -> bool,
+        field: &impl GroupField,
+    ) -> Self {
+        AbortedGroupMatch {
+            kind: kind.to_string(),
+            rules: rules.to_string(),
+            scores: (0..players.len()).map(|i| field.get_score(i)).collect(),
+            lines: (0..players.len()).map(|i| field.get_lines(i)).collect(),
+            bots: players.iter().map(|player| is_bot(*player)).collect(),
+            players,
+            time: now(),
+        }
+    }
+}
+
+// Full state of running match, including computer players
+#[derive(Serialize, Deserialize)]
+pub struct MatchSnapshot {
+    pub player_a: u32,
+    pub player_b: u32,
+    pub field: TetrisPair,
+    // Bots by their user ids
+    pub bots: Vec<(u32, Bot)>,
+}
+
+//
+// Records of matches which couldn't be finished and snapshots of matches to resume
+//
+pub struct MatchRecords {
+    persy: Persy,
+}
+
+impl MatchRecords {
+    // Create segments if they don't exist yet
+    pub fn open(persy: Persy) -> Result<MatchRecords, Error> {
+        let mut tx = persy.begin()?;
+        for segment in [
+            ABORTED_MATCHES_SEGMENT,
+            ABORTED_GROUP_MATCHES_SEGMENT,
+            MATCH_SNAPSHOTS_SEGMENT,
+        ] {
+            if !tx.exists_segment(segment)? {
+                tx.create_segment(segment)?;
+            }
+        }
+        tx.prepare()?.commit()?;
+        Ok(MatchRecords { persy })
+    }
+
+    // Record all matches in one transaction
+    pub fn record_aborted(&self, matches: &[AbortedMatch]) -> Result<(), Error> {
+        self.insert_all(ABORTED_MATCHES_SEGMENT, matches)
+    }
+
+    pub fn record_aborted_groups(&self, matches: &[AbortedGroupMatch]) -> Result<(), Error> {
+        self.insert_all(ABORTED_GROUP_MATCHES_SEGMENT, matches)
+    }
+
+    // Get all aborted matches. Records which can't be read are skipped
+    pub fn get_aborted(&self) -> Result<Vec<AbortedMatch>, Error> {
+        self.scan_all(ABORTED_MATCHES_SEGMENT)
+    }
+
+    pub fn get_aborted_groups(&self) -> Result<Vec<AbortedGroupMatch>, Error> {
+        self.scan_all(ABORTED_GROUP_MATCHES_SEGMENT)
+    }
+
+    // Insert json records in one transaction
+    fn insert_all<T: Serialize>(&self, segment: &str, records: &[T]) -> Result<(), Error> {
+        let mut tx = self.persy.begin()?;
+        for record in records {
+            let record = serde_json::to_vec(record).unwrap();
+            tx.insert(segment, &record)?;
+        }
+        tx.prepare()?.commit()?;
+        Ok(())
+    }
+
+    // Read json records, ones which can't be read are skipped
+    fn scan_all<T: for<'de> Deserialize<'de>>(&self, segment: &str) -> Result<Vec<T>, Error> {
+        let mut records = Vec::new();
+        for (_, record) in self.persy.scan(segment)? {
+            if let Ok(record) = serde_json::from_slice(&record) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    // Replace saved snapshots with the given ones
+    pub fn save_snapshots(&self, snapshots: &[MatchSnapshot]) -> Result<(), Error> {
+        let saved: Vec<PersyId> = self
+            .persy
+            .scan(MATCH_SNAPSHOTS_SEGMENT)?
+            .map(|(id, _)| id)
+            .collect();
+        let mut tx = self.persy.begin()?;
+        for id in saved {
+            tx.delete(MATCH_SNAPSHOTS_SEGMENT, &id)?;
+        }
+        for snapshot in snapshots {
+            let record = serde_json::to_vec(snapshot).unwrap();
+            tx.insert(MATCH_SNAPSHOTS_SEGMENT, &record)?;
+        }
+        tx.prepare()?.commit()?;
+        Ok(())
+    }
+
+    // Get saved snapshots and remove them, so each match is resumed only once.
+    // Snapshots which can't be read, e.g. saved by older version, are dropped
+    pub fn take_snapshots(&self) -> Result<Vec<MatchSnapshot>, Error> {
+        let mut snapshots = Vec::new();
+        let mut tx = self.persy.begin()?;
+        for (id, record) in self.persy.scan(MATCH_SNAPSHOTS_SEGMENT)? {
+            if let Ok(snapshot) = serde_json::from_slice(&record) {
+                snapshots.push(snapshot);
+            }
+            tx.delete(MATCH_SNAPSHOTS_SEGMENT, &id)?;
+        }
+        tx.prepare()?.commit()?;
+        Ok(snapshots)
+    }
+}
@@ -0,0 +1,540 @@
+use rocket::data::{self, Data, FromData, ToByteUnit};
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::serde::json::serde_json;
+use serde::{Deserialize, Serialize};
+
+use crate::frames::{BoardFrame, Frame};
+use crate::solo::{SoloResult, SoloStatus};
+use std::time::Duration;
+
+use tetris_engine::tetris::Action;
+use tetris_engine::{
+    Battle, CoopMatch, GroupField, Lifecycle, MatchPhase, Pause, PauseState, PlayerSide, Targeting,
+    TeamMatch,
+};
+
+// Action sent by client, `time` is client's timestamp in milliseconds
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct TimedAction {
+    pub action: Action,
+    #[serde(default)]
+    pub time: Option<u64>,
+}
+
+//
+// Body of /action request, one or more actions. Accepted in two forms:
+// - JSON array of actions: [{"action": "MoveLeft", "time": 1200}, {"action": "Drop"}]
+// - compact text with space separated action codes and optional times: "L:1200 X"
+// Action without time happens at the time of the action before it
+//
+pub struct ActionBatch(pub Vec<TimedAction>);
+
+impl ActionBatch {
+    // Actions in order of client timestamps, actions with the same time keep body order
+    pub fn into_ordered(self) -> Vec<Action> {
+        let mut last_time = 0;
+        let mut actions: Vec<(u64, Action)> = self
+            .0
+            .into_iter()
+            .map(|timed_action| {
+                last_time = timed_action.time.unwrap_or(last_time);
+                (last_time, timed_action.action)
+            })
+            .collect();
+        // Stable sort
+        actions.sort_by_key(|(time, _)| *time);
+        actions.into_iter().map(|(_, action)| action).collect()
+    }
+
+    // Parse JSON form of actions batch
+    pub fn parse_json(text: &str) -> Result<ActionBatch, String> {
+        serde_json::from_str(text)
+            .map(ActionBatch)
+            .map_err(|e| e.to_string())
+    }
+    // Parse compact form of actions batch
+    pub fn parse_compact(text: &str) -> Result<ActionBatch, String> {
+        text.split_whitespace()
+            .map(|item| {
+                let (code, time) = match item.split_once(':') {
+                    Some((code, time)) => (code, Some(time)),
+                    None => (item, None),
+                };
+                let action = action_from_code(code)
+                    .ok_or_else(|| format!("Unknown action code: {}", code))?;
+                let time = time
+                    .map(|time| {
+                        time.parse()
+                            .map_err(|_| format!("Invalid action time: {}", time))
+                    })
+                    .transpose()?;
+                Ok(TimedAction { action, time })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(ActionBatch)
+    }
+}
+
+// Action codes of compact actions batch
+fn action_from_code(code: &str) -> Option<Action> {
+    match code {
+        "L" => Some(Action::MoveLeft),
+        "R" => Some(Action::MoveRight),
+        "D" => Some(Action::MoveDown),
+        "A" => Some(Action::RotateLeft),
+        "C" => Some(Action::RotateRight),
+        "X" => Some(Action::Drop),
+        _ => None,
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromData<'r> for ActionBatch {
+    type Error = String;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = request.limits().get("action").unwrap_or(1.kibibytes());
+        let text = match data.open(limit).into_string().await {
+            Ok(text) if text.is_complete() => text.into_inner(),
+            Ok(_) => {
+                let error = "Too many actions".to_string();
+                return data::Outcome::Error((Status::PayloadTooLarge, error));
+            }
+            Err(e) => return data::Outcome::Error((Status::InternalServerError, e.to_string())),
+        };
+        let batch = if request.content_type().is_some_and(|ct| ct.is_json()) {
+            ActionBatch::parse_json(&text)
+        } else {
+            ActionBatch::parse_compact(&text)
+        };
+        match batch {
+            Ok(batch) => data::Outcome::Success(batch),
+            Err(e) => data::Outcome::Error((Status::BadRequest, e)),
+        }
+    }
+}
+
+//
+// Messages of the WebSocket transport. Each message is a JSON object
+// with "type" field containing message kind
+//
+
+// Message sent by client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    // Player's action. Sequence number must grow, actions with already seen numbers are dropped
+    Input { seq: u64, action: Action },
+    // Request a keyframe, sent when client detects a gap in frame numbers
+    Resync,
+    // Request pause, match is paused when opponent requests it too
+    Pause,
+    // Resume paused game after countdown or withdraw pause request
+    Resume,
+    // Confirm being ready to start the match
+    Ready,
+    // Choose how opponents receiving garbage are chosen in battle royale
+    Target { targeting: Targeting },
+}
+
+// Message sent by server
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage<'a> {
+    // Game state frame, `ack` is the sequence number of the last accepted input
+    State {
+        ack: u64,
+        #[serde(flatten)]
+        frame: &'a Frame,
+    },
+    // Change of player's status
+    MatchEvent {
+        event: MatchEvent,
+    },
+    // Number of spectators watching player's match
+    Spectators {
+        count: usize,
+    },
+    // Progress of single player game
+    SoloStatus {
+        status: &'a SoloStatus,
+    },
+    // Single player game is finished, connection is closed after this message
+    SoloFinished {
+        result: &'a SoloResult,
+    },
+    // Pause state is changed
+    Pause(PauseInfo),
+    // Match moved to the next phase or player confirmed being ready
+    Phase(PhaseInfo),
+    // Number of players waiting in battle royale lobby
+    Lobby {
+        count: usize,
+    },
+    // Battle royale state is changed
+    Battle(BattleInfo),
+    // Team match state is changed
+    Team(TeamInfo),
+    // Co-op game state is changed
+    Coop(CoopInfo),
+}
+
+// Pause state from player's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseStatus {
+    Running,
+    RequestedByPlayer,
+    RequestedByOpponent,
+    Paused,
+    Countdown,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PauseInfo {
+    pub status: PauseStatus,
+    pub pauses_left: usize,
+    // Time before the game continues, used with countdown status
+    pub countdown_ms: u64,
+}
+
+impl PauseInfo {
+    pub fn new(pause: &Pause, player: PlayerSide, step: Duration) -> Self {
+        let (status, countdown_steps) = match pause.get_state() {
+            PauseState::Running => (PauseStatus::Running, 0),
+            PauseState::Requested(side) if side == player => (PauseStatus::RequestedByPlayer, 0),
+            PauseState::Requested(_) => (PauseStatus::RequestedByOpponent, 0),
+            PauseState::Paused => (PauseStatus::Paused, 0),
+            PauseState::Countdown(steps) => (PauseStatus::Countdown, steps),
+        };
+        PauseInfo {
+            status,
+            pauses_left: pause.get_pauses_left(),
+            countdown_ms: countdown_steps as u64 * step.as_millis() as u64,
+        }
+    }
+
+    // Countdown time is not compared, so the info is sent only when countdown starts
+    pub fn changed(&self, last: &Option<PauseInfo>) -> bool {
+        last.is_none_or(|last| last.status != self.status || last.pauses_left != self.pauses_left)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PhaseStatus {
+    Matched,
+    ReadyCheck,
+    Countdown,
+    Playing,
+    Finished,
+}
+
+// Phase of the match from player's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PhaseInfo {
+    pub phase: PhaseStatus,
+    pub player_ready: bool,
+    pub opponent_ready: bool,
+    // Time before the match starts, used with countdown phase
+    pub countdown_ms: u64,
+}
+
+impl PhaseInfo {
+    pub fn new(lifecycle: &Lifecycle, player: PlayerSide, step: Duration) -> Self {
+        let (phase, countdown_steps) = phase_status(lifecycle.get_phase());
+        PhaseInfo {
+            phase,
+            player_ready: lifecycle.is_ready(player),
+            opponent_ready: lifecycle.is_ready(player.opponent()),
+            countdown_ms: countdown_steps as u64 * step.as_millis() as u64,
+        }
+    }
+
+    // Same as PauseInfo::changed, countdown time is not compared
+    pub fn changed(&self, last: &Option<PhaseInfo>) -> bool {
+        last.is_none_or(|last| {
+            last.phase != self.phase
+                || last.player_ready != self.player_ready
+                || last.opponent_ready != self.opponent_ready
+        })
+    }
+}
+
+// Phase with number of steps left in countdown
+fn phase_status(phase: MatchPhase) -> (PhaseStatus, usize) {
+    match phase {
+        MatchPhase::Matched => (PhaseStatus::Matched, 0),
+        MatchPhase::ReadyCheck => (PhaseStatus::ReadyCheck, 0),
+        MatchPhase::Countdown(steps) => (PhaseStatus::Countdown, steps),
+        MatchPhase::Playing => (PhaseStatus::Playing, 0),
+        MatchPhase::Finished => (PhaseStatus::Finished, 0),
+    }
+}
+
+// Battle royale from player's point of view. Player's own board goes first in frames,
+// then other boards in order of players. `target` is position of the target board there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BattleInfo {
+    pub phase: PhaseStatus,
+    // Time before the battle starts, used with countdown phase
+    pub countdown_ms: u64,
+    pub players: usize,
+    pub players_left: usize,
+    pub targeting: Targeting,
+    pub target: Option<usize>,
+    // Opponents knocked out by the player
+    pub kos: usize,
+    // Final place of the player, 1 for the winner
+    pub placement: Option<usize>,
+    // Placement is shared with players knocked out at the same time, a draw for the first place
+    pub tied: bool,
+}
+
+//
+// State of a match of many players sent along with frames, SSE event is named by `EVENT`.
+// Implemented for each kind of field of GroupGames
+//
+pub trait GroupInfo: Serialize + Clone + Send + Sized {
+    type Field: GroupField;
+    const EVENT: &'static str;
+
+    fn new(field: &Self::Field, player: usize, step: Duration) -> Option<Self>;
+    // Same as PauseInfo::changed, countdown time is not compared
+    fn changed(&self, last: &Option<Self>) -> bool;
+    fn message(self) -> ServerMessage<'static>;
+    // Handle client message specific to the kind of match
+    fn command(_field: &mut Self::Field, _player: usize, _message: ClientMessage) {}
+    // Boards shown to the player in order of GroupField::board_order
+    fn boards(field: &Self::Field, player: usize) -> Vec<BoardFrame> {
+        field
+            .board_order(player)
+            .into_iter()
+            .filter_map(|i| field.get_tetris(i))
+            .map(BoardFrame::new)
+            .collect()
+    }
+}
+
+impl GroupInfo for BattleInfo {
+    type Field = Battle;
+    const EVENT: &'static str = "battle";
+
+    fn new(battle: &Battle, player: usize, step: Duration) -> Option<Self> {
+        let standing = battle.get_standing(player)?;
+        let (phase, countdown_steps) = phase_status(battle.get_phase());
+        Some(BattleInfo {
+            phase,
+            countdown_ms: countdown_steps as u64 * step.as_millis() as u64,
+            players: battle.players(),
+            players_left: battle.get_players_left(),
+            targeting: standing.targeting,
+            target: standing
+                .target
+                .map(|target| if target < player { target + 1 } else { target }),
+            kos: standing.kos,
+            placement: standing.placement,
+            tied: standing.tied,
+        })
+    }
+
+    fn changed(&self, last: &Option<BattleInfo>) -> bool {
+        last.is_none_or(|last| {
+            let last = BattleInfo {
+                countdown_ms: self.countdown_ms,
+                ..last
+            };
+            last != *self
+        })
+    }
+
+    fn message(self) -> ServerMessage<'static> {
+        ServerMessage::Battle(self)
+    }
+
+    fn command(battle: &mut Battle, player: usize, message: ClientMessage) {
+        if let ClientMessage::Target { targeting } = message {
+            battle.set_targeting(player, targeting);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TeamResult {
+    Won,
+    Lost,
+    // Both teams were eliminated at once
+    Draw,
+}
+
+// Team match from player's point of view. Boards in frames go in order: player's own board,
+// the teammate, then the opposing team
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TeamInfo {
+    pub phase: PhaseStatus,
+    // Time before the match starts, used with countdown phase
+    pub countdown_ms: u64,
+    // Boards which are out of the game, in order of boards in frames
+    pub out: Vec<bool>,
+    pub team_lines: usize,
+    pub opponent_lines: usize,
+    // Set when the match is finished
+    pub result: Option<TeamResult>,
+}
+
+impl GroupInfo for TeamInfo {
+    type Field = TeamMatch;
+    const EVENT: &'static str = "team";
+
+    fn new(team_match: &TeamMatch, player: usize, step: Duration) -> Option<Self> {
+        if player >= team_match.players() {
+            return None;
+        }
+        let team = TeamMatch::get_team(player);
+        let (phase, countdown_steps) = phase_status(team_match.get_phase());
+        let result = team_match
+            .is_finished()
+            .then(|| match team_match.get_winner() {
+                Some(winner) if winner == team => TeamResult::Won,
+                Some(_) => TeamResult::Lost,
+                None => TeamResult::Draw,
+            });
+        Some(TeamInfo {
+            phase,
+            countdown_ms: countdown_steps as u64 * step.as_millis() as u64,
+            out: team_match
+                .board_order(player)
+                .into_iter()
+                .map(|i| team_match.is_out(i))
+                .collect(),
+            team_lines: team_match.get_team_lines(team),
+            opponent_lines: team_match.get_team_lines((team + 1) % tetris_engine::teams::TEAMS),
+            result,
+        })
+    }
+
+    fn changed(&self, last: &Option<TeamInfo>) -> bool {
+        last.as_ref().is_none_or(|last| {
+            let last = TeamInfo {
+                countdown_ms: self.countdown_ms,
+                ..last.clone()
+            };
+            last != *self
+        })
+    }
+
+    fn message(self) -> ServerMessage<'static> {
+        ServerMessage::Team(self)
+    }
+}
+
+// Co-op game from player's point of view. The only board in frames is the shared field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CoopInfo {
+    pub phase: PhaseStatus,
+    // Time before the game starts, used with countdown phase
+    pub countdown_ms: u64,
+    pub score: usize,
+    pub lines: usize,
+    // Partner left, player continues alone
+    pub partner_left: bool,
+}
+
+impl GroupInfo for CoopInfo {
+    type Field = CoopMatch;
+    const EVENT: &'static str = "coop";
+
+    fn new(coop: &CoopMatch, player: usize, step: Duration) -> Option<Self> {
+        let tetris = coop.get_coop_tetris();
+        if player >= tetris.players() {
+            return None;
+        }
+        let (phase, countdown_steps) = phase_status(coop.get_phase());
+        Some(CoopInfo {
+            phase,
+            countdown_ms: countdown_steps as u64 * step.as_millis() as u64,
+            score: tetris.get_score(),
+            lines: tetris.get_lines(),
+            partner_left: (0..tetris.players()).any(|i| i != player && tetris.has_left(i)),
+        })
+    }
+
+    fn changed(&self, last: &Option<CoopInfo>) -> bool {
+        last.is_none_or(|last| {
+            let last = CoopInfo {
+                countdown_ms: self.countdown_ms,
+                ..last
+            };
+            last != *self
+        })
+    }
+
+    fn message(self) -> ServerMessage<'static> {
+        ServerMessage::Coop(self)
+    }
+
+    fn boards(coop: &CoopMatch, player: usize) -> Vec<BoardFrame> {
+        vec![BoardFrame::coop(coop.get_coop_tetris(), player)]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchEvent {
+    // Player is in the wait list
+    Waiting,
+    // Opponent found, state frames follow
+    Matched,
+    // Match is finished, aborted or its ready check expired, player returns to the wait list
+    MatchEnded,
+    // Session is closed by moderator, client should not reconnect
+    Kicked,
+    // No opponent found within wait timeout, session is closed
+    WaitTimeout,
+    // Server is stopping, client may reconnect after restart
+    Shutdown,
+    // Opponent lost connection, match is paused until opponent returns or grace period ends
+    OpponentReconnecting,
+    // Opponent returned and match continues
+    OpponentReturned,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn action_batches_keep_order() {
+        let compact = ActionBatch::parse_compact("L:1200 X R:1100 A").unwrap();
+        assert_eq!(
+            compact.into_ordered(),
+            [
+                Action::MoveRight,
+                Action::RotateLeft,
+                Action::MoveLeft,
+                Action::Drop
+            ]
+        );
+        let json =
+            ActionBatch::parse_json(r#"[{"action":"MoveLeft","time":1200},{"action":"Drop"}]"#)
+                .unwrap();
+        assert_eq!(json.into_ordered(), [Action::MoveLeft, Action::Drop]);
+        let untimed = ActionBatch::parse_compact("C D").unwrap();
+        assert_eq!(
+            untimed.into_ordered(),
+            [Action::RotateRight, Action::MoveDown]
+        );
+    }
+
+    #[test]
+    fn invalid_action_batches_are_rejected() {
+        assert!(ActionBatch::parse_compact("L Q").is_err());
+        assert!(ActionBatch::parse_compact("L:soon").is_err());
+        assert!(ActionBatch::parse_compact("L:-5").is_err());
+        assert!(ActionBatch::parse_json(r#"[{"action":"Teleport"}]"#).is_err());
+        assert!(ActionBatch::parse_json(r#"[{"action":"Drop","time":"soon"}]"#).is_err());
+        assert!(ActionBatch::parse_json(r#"[{"action":"BottomRefill","time":10}]"#).is_err());
+    }
+}
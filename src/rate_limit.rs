@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Sustained rate of player's actions. Fast human players do about 10 actions per second
+const ACTIONS_PER_SECOND: f64 = 20.0;
+// Number of actions allowed in a burst
+const ACTIONS_BURST: f64 = 30.0;
+// Number of dropped actions after which player is flagged for moderator
+const FLAG_THRESHOLD: usize = 100;
+// Buckets of players inactive for this time are removed
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Token bucket of single player
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    dropped: usize,
+}
+
+//
+// Limits rate of actions sent by each player. Actions above the limit are dropped,
+// players who send too many of them are flagged
+//
+pub struct RateLimiter<K> {
+    buckets: Mutex<(HashMap<K, Bucket>, Instant)>,
+}
+
+impl<K: Copy + Eq + Hash> RateLimiter<K> {
+    pub fn new() -> Self {
+        RateLimiter {
+            buckets: Mutex::new((HashMap::new(), Instant::now())),
+        }
+    }
+
+    // Check if player is allowed to perform one more action now
+    pub fn allow(&self, player: K) -> bool {
+        let now = Instant::now();
+        let mut guard = self.buckets.lock().unwrap();
+        let (buckets, last_cleanup) = &mut *guard;
+        if now.duration_since(*last_cleanup) > IDLE_TIMEOUT {
+            buckets.retain(|_, bucket| {
+                now.duration_since(bucket.updated) < IDLE_TIMEOUT
+                    || bucket.dropped >= FLAG_THRESHOLD
+            });
+            *last_cleanup = now;
+        }
+        let bucket = buckets.entry(player).or_insert(Bucket {
+            tokens: ACTIONS_BURST,
+            updated: now,
+            dropped: 0,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * ACTIONS_PER_SECOND).min(ACTIONS_BURST);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            bucket.dropped += 1;
+            false
+        }
+    }
+
+    // Players with implausible action rate and number of their dropped actions
+    pub fn flagged(&self) -> Vec<(K, usize)> {
+        let guard = self.buckets.lock().unwrap();
+        guard
+            .0
+            .iter()
+            .filter(|(_, bucket)| bucket.dropped >= FLAG_THRESHOLD)
+            .map(|(player, bucket)| (*player, bucket.dropped))
+            .collect()
+    }
+}
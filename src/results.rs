@@ -0,0 +1,57 @@
+use persy::{Persy, PersyId, ValueMode};
+use rocket::serde::json::serde_json;
+
+use crate::error::Error;
+use crate::solo::SoloResult;
+
+// Persy segment with solo game results as json records
+const SOLO_RESULTS_SEGMENT: &str = "solo_results";
+// Persy index from user id to ids of user's result records
+const SOLO_RESULTS_INDEX: &str = "solo_results_by_user";
+
+//
+// Results of finished single player games
+//
+pub struct SoloResults {
+    persy: Persy,
+}
+
+impl SoloResults {
+    // Create segment and index if they don't exist yet
+    pub fn open(persy: Persy) -> Result<SoloResults, Error> {
+        let mut tx = persy.begin()?;
+        if !tx.exists_segment(SOLO_RESULTS_SEGMENT)? {
+            tx.create_segment(SOLO_RESULTS_SEGMENT)?;
+        }
+        if !tx.exists_index(SOLO_RESULTS_INDEX)? {
+            tx.create_index::<u32, PersyId>(SOLO_RESULTS_INDEX, ValueMode::Cluster)?;
+        }
+        tx.prepare()?.commit()?;
+        Ok(SoloResults { persy })
+    }
+
+    pub fn record(&self, user_id: u32, result: &SoloResult) -> Result<(), Error> {
+        let record = serde_json::to_vec(result).unwrap();
+        let mut tx = self.persy.begin()?;
+        let id = tx.insert(SOLO_RESULTS_SEGMENT, &record)?;
+        tx.put::<u32, PersyId>(SOLO_RESULTS_INDEX, user_id, id)?;
+        tx.prepare()?.commit()?;
+        Ok(())
+    }
+
+    // Get all results of the user. Records which can't be read are skipped
+    pub fn get_user_results(&self, user_id: u32) -> Result<Vec<SoloResult>, Error> {
+        let mut results = Vec::new();
+        for id in self
+            .persy
+            .get::<u32, PersyId>(SOLO_RESULTS_INDEX, &user_id)?
+        {
+            if let Some(record) = self.persy.read(SOLO_RESULTS_SEGMENT, &id)? {
+                if let Ok(result) = serde_json::from_slice(&record) {
+                    results.push(result);
+                }
+            }
+        }
+        Ok(results)
+    }
+}
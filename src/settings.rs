@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rocket::serde::json::serde_json;
+use serde::{de, Deserialize, Deserializer};
+use tetris_engine::{BotDifficulty, PieceSet, RuleSet};
+
+//
+// Server and game settings read from Rocket.toml. Every key can be overridden
+// by environment variable with ROCKET_ prefix, e.g. ROCKET_TICK_MS=20
+//
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Persy database file
+    pub database: PathBuf,
+    // Rules of matches for players who didn't choose any
+    #[serde(deserialize_with = "from_name")]
+    pub rules: RuleSet,
+    // Board size of the default rules instead of the one of the preset
+    pub board_cols: Option<usize>,
+    pub board_rows: Option<usize>,
+    pub board_hidden_rows: Option<usize>,
+    // Pieces of the default rules: preset name or JSON file of custom piece set
+    #[serde(deserialize_with = "from_name")]
+    pub pieces: PieceSet,
+    pub pieces_file: Option<PathBuf>,
+    // Presets players can choose instead of the default rules by preset name, with the same
+    // board size and pieces as the default rules. Set by apply_rules
+    #[serde(skip)]
+    pub presets: HashMap<String, RuleSet>,
+    // Milliseconds between game steps of matches
+    pub tick_ms: u64,
+    // Seconds before waiting player is removed from the wait list, 0 to wait forever
+    pub wait_timeout: u64,
+    // Seconds before waiting player is matched with a bot, 0 to disable bots
+    pub bot_wait: u64,
+    #[serde(deserialize_with = "from_name")]
+    pub bot_difficulty: BotDifficulty,
+    // Seconds disconnected player can return to the paused match before it is removed
+    pub reconnect_grace: u64,
+    // Battle royale starts when the lobby has max players, or min players waited
+    // for battle_wait seconds. Max players is limited by the engine to 16
+    pub battle_min_players: usize,
+    pub battle_max_players: usize,
+    pub battle_wait: u64,
+    // Save running matches on shutdown and resume them after restart,
+    // otherwise they are recorded as aborted
+    pub resume_matches: bool,
+    // Credentials of the admin pages, admin pages are disabled if not set
+    pub admin: Option<AdminCredentials>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminCredentials {
+    pub user: String,
+    pub password: String,
+}
+
+impl Settings {
+    pub fn tick(&self) -> Duration {
+        Duration::from_millis(self.tick_ms.max(1))
+    }
+    pub fn wait_timeout(&self) -> Option<Duration> {
+        (self.wait_timeout > 0).then(|| Duration::from_secs(self.wait_timeout))
+    }
+    pub fn reconnect_grace(&self) -> Duration {
+        Duration::from_secs(self.reconnect_grace)
+    }
+    pub fn battle_wait(&self) -> Duration {
+        Duration::from_secs(self.battle_wait)
+    }
+    pub fn bot_wait(&self) -> Option<Duration> {
+        (self.bot_wait > 0).then(|| Duration::from_secs(self.bot_wait))
+    }
+    // Apply board size and pieces to the default rules and to the presets players can choose,
+    // check their boards are within the engine limits and fit the pieces
+    pub fn apply_rules(&mut self) -> Result<(), String> {
+        if let Some(path) = &self.pieces_file {
+            let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+            let text = std::fs::read_to_string(path).map_err(|e| error(&e))?;
+            let def = serde_json::from_str(&text).map_err(|e| error(&e))?;
+            self.pieces = PieceSet::from_def(def).map_err(|e| error(&e))?;
+        }
+        self.rules = self.customize_rules(self.rules.clone())?;
+        self.presets = RuleSet::presets()
+            .into_iter()
+            .map(|rules| Ok((rules.name.clone(), self.customize_rules(rules)?)))
+            .collect::<Result<_, String>>()?;
+        Ok(())
+    }
+    // Changed rules get their own name, so players are matched only with same boards
+    fn customize_rules(&self, mut rules: RuleSet) -> Result<RuleSet, String> {
+        let board = (rules.cols, rules.rows, rules.hidden_rows);
+        rules.cols = self.board_cols.unwrap_or(rules.cols);
+        rules.rows = self.board_rows.unwrap_or(rules.rows);
+        rules.hidden_rows = self.board_hidden_rows.unwrap_or(rules.hidden_rows);
+        if (rules.cols, rules.rows, rules.hidden_rows) != board {
+            rules.name = format!(
+                "{}-{}x{}+{}",
+                rules.name, rules.cols, rules.rows, rules.hidden_rows
+            );
+        }
+        if self.pieces != rules.pieces {
+            rules.name = format!("{}+{}", rules.name, self.pieces.name);
+            rules.pieces = self.pieces.clone();
+        }
+        rules.validate()?;
+        Ok(rules)
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            database: PathBuf::from("gameserver.db"),
+            rules: RuleSet::default(),
+            board_cols: None,
+            board_rows: None,
+            board_hidden_rows: None,
+            pieces: PieceSet::default(),
+            pieces_file: None,
+            presets: HashMap::new(),
+            tick_ms: 10,
+            wait_timeout: 0,
+            bot_wait: 30,
+            bot_difficulty: BotDifficulty::MEDIUM,
+            reconnect_grace: 30,
+            battle_min_players: 3,
+            battle_max_players: 16,
+            battle_wait: 20,
+            resume_matches: true,
+            admin: None,
+        }
+    }
+}
+
+// Presets are given by name in config, e.g. rules = "chaos"
+fn from_name<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+{
+    let name = String::deserialize(deserializer)?;
+    name.parse()
+        .map_err(|_| de::Error::custom(format!("unknown preset: {}", name)))
+}
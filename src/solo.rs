@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use tetris_engine::pause::Pause;
+use tetris_engine::tetris::{Action, StepResult, Tetris};
+use tetris_engine::RuleSet;
+
+// Duration of single game step in milliseconds
+pub const STEP_MS: usize = 10;
+pub const STEP: Duration = Duration::from_millis(STEP_MS as u64);
+
+// Marathon: level grows every 10 lines, game is completed after level 15
+const MARATHON_MAX_LEVEL: usize = 15;
+const MARATHON_LINES_PER_LEVEL: usize = 10;
+// Sprint: clear 40 lines as fast as possible
+const SPRINT_LINES: usize = 40;
+// Ultra: get max score in 2 minutes
+const ULTRA_STEPS: usize = 2 * 60 * 1000 / STEP_MS;
+// Pauses allowed per game
+const PAUSES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SoloMode {
+    Marathon,
+    Sprint,
+    Ultra,
+}
+
+impl std::str::FromStr for SoloMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "marathon" => Ok(SoloMode::Marathon),
+            "sprint" => Ok(SoloMode::Sprint),
+            "ultra" => Ok(SoloMode::Ultra),
+            _ => Err(()),
+        }
+    }
+}
+
+// Current progress of the game
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SoloStatus {
+    pub lines: usize,
+    pub level: usize,
+    pub score: usize,
+}
+
+// Result of finished game
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoloResult {
+    pub mode: SoloMode,
+    // Goal of the mode is reached, false if game is over before it
+    pub completed: bool,
+    pub lines: usize,
+    pub level: usize,
+    pub score: usize,
+    pub time_ms: usize,
+    // Game is played by bot account. Absent in results recorded before bot accounts were added
+    #[serde(default)]
+    pub bot: bool,
+}
+
+//
+// Single player game on one board with end condition depending on the mode.
+// Time is counted in game steps, so the result doesn't depend on server load
+//
+pub struct SoloGame {
+    mode: SoloMode,
+    tetris: Tetris,
+    steps: usize,
+    // Game is played by bot account
+    bot: bool,
+    // Paused game doesn't count time
+    pause: Pause,
+    result: Option<SoloResult>,
+}
+
+impl SoloGame {
+    pub fn new(rules: &RuleSet, mode: SoloMode, bot: bool) -> Self {
+        SoloGame {
+            mode,
+            tetris: Tetris::new(rules),
+            steps: 0,
+            bot,
+            pause: Pause::new(PAUSES),
+            result: None,
+        }
+    }
+
+    // Actions are dropped while the game is paused
+    pub fn add_action(&mut self, action: Action) {
+        if !self.pause.is_paused() {
+            self.tetris.add_action(action);
+        }
+    }
+
+    // Pause at once, there is no opponent to agree. Returns true if the game is paused
+    pub fn request_pause(&mut self) -> bool {
+        self.pause.request_now()
+    }
+
+    pub fn resume(&mut self) {
+        self.pause.resume();
+    }
+
+    pub fn get_pause(&self) -> &Pause {
+        &self.pause
+    }
+
+    // Perform one game step. Returns result when the game is finished
+    pub fn step(&mut self) -> Option<&SoloResult> {
+        if self.result.is_none() && self.pause.step() {
+            self.steps += 1;
+            let game_over = self.tetris.step() == StepResult::GameOver;
+            let lines = self.tetris.get_lines();
+            if self.mode == SoloMode::Marathon {
+                let level = (1 + lines / MARATHON_LINES_PER_LEVEL).min(MARATHON_MAX_LEVEL);
+                if level != self.tetris.get_level() {
+                    self.tetris.set_level(level);
+                }
+            }
+            let completed = match self.mode {
+                SoloMode::Marathon => lines >= MARATHON_MAX_LEVEL * MARATHON_LINES_PER_LEVEL,
+                SoloMode::Sprint => lines >= SPRINT_LINES,
+                SoloMode::Ultra => self.steps >= ULTRA_STEPS,
+            };
+            if completed || game_over {
+                self.result = Some(SoloResult {
+                    mode: self.mode,
+                    completed,
+                    lines,
+                    level: self.tetris.get_level(),
+                    score: self.tetris.get_score(),
+                    time_ms: self.steps * STEP_MS,
+                    bot: self.bot,
+                });
+            }
+        }
+        self.result.as_ref()
+    }
+
+    pub fn get_tetris(&self) -> &Tetris {
+        &self.tetris
+    }
+
+    pub fn get_status(&self) -> SoloStatus {
+        SoloStatus {
+            lines: self.tetris.get_lines(),
+            level: self.tetris.get_level(),
+            score: self.tetris.get_score(),
+        }
+    }
+}
@@ -115,57 +115,392 @@ class TetrisDisplay {
 
 }
 
+// Restores boards state from keyframes and delta frames sent by server
+class TetrisFrameDecoder {
+    seq = null;
+    boards = [];
+
+    // Apply frame. Returns false if frame can't be applied and resync is needed
+    apply(frame) {
+        if (frame.frame === 'key') {
+            this.boards = frame.boards;
+        } else if (this.seq !== null && frame.seq === this.seq + 1) {
+            frame.boards.forEach((delta, i) => TetrisFrameDecoder.applyDelta(this.boards[i], delta));
+        } else {
+            this.seq = null;
+            return false;
+        }
+        this.seq = frame.seq;
+        return true;
+    }
+
+    static applyDelta(board, delta) {
+        if (delta.cells) {
+            for (const [x, y, cell] of delta.cells) {
+                board.field[y][x] = cell;
+            }
+        }
+        if (delta.piece !== undefined) {
+            board.piece = delta.piece;
+        }
+        if (delta.preview !== undefined) {
+            board.preview = delta.preview;
+        }
+        if (delta.game_over !== undefined) {
+            board.game_over = delta.game_over;
+        }
+    }
+
+    // Board data for display with falling piece drawn on the field
+    static compose(board) {
+        const field = board.field.map((row) => row.slice());
+        if (board.piece) {
+            for (const [x, y] of board.piece.cells) {
+                field[y][x] = board.piece.cell_type;
+            }
+        }
+        return { cols: board.cols, rows: board.rows, field: field, preview: board.preview, game_over: board.game_over };
+    }
+
+    // Boards for display, player's own board goes first
+    state() {
+        return this.boards.map(TetrisFrameDecoder.compose);
+    }
+}
+
 class TetrisClient {
 
     url;
+    // Path of game stream, e.g. '/matches/1' for spectators or '/solo/sprint' for single player game
+    path;
+    // Query string of game stream, e.g. '?rules=chaos' to choose rules of the match
+    query = '';
     sse;
+    ws = null;
+    seq = 0;
+    decoder = new TetrisFrameDecoder();
+    // Called with number of spectators watching the match
+    onSpectators = null;
+    // Called with true when opponent lost connection and with false when opponent returned
+    onOpponentReconnecting = null;
+    // Called with match phase, ready flags and countdown before the start
+    onPhase = null;
+    // Called with pause status, pauses left and countdown before resuming
+    onPause = null;
+    // Last pause info received from server
+    pauseInfo = null;
+    // Called with number of players in battle royale lobby and with battle state
+    onLobby = null;
+    onBattle = null;
+    // Called with team match state
+    onTeam = null;
+    // Called with co-op game state
+    onCoop = null;
+    // Called with progress and with result of single player game
+    onSoloStatus = null;
+    onSoloFinished = null;
     display_player;
-    display_opponent;
+    display_opponent = null;
+    // Boards of other players in battle royale and team matches, in order of boards in frames
+    display_others = [];
 
-    // Contructor accepts canvas
-    constructor(canvas_player, canvas_opponemt, url) {
+    // Contructor accepts canvas, opponent's canvas is not needed for single player game
+    constructor(canvas_player, canvas_opponemt, url, path = '') {
         this.url = url;
+        this.path = path;
         this.display_player = new TetrisDisplay(canvas_player, 20, 10);
-        this.display_opponent = new TetrisDisplay(canvas_opponemt, 20, 10);
+        if (canvas_opponemt) {
+            this.display_opponent = new TetrisDisplay(canvas_opponemt, 20, 10);
+        }
+    }
+
+    // Add canvas for the next board of battle royale or team match
+    addOtherCanvas(canvas) {
+        this.display_others.push(new TetrisDisplay(canvas, 20, 10));
+    }
+
+    // Apply state frame and redraw. Returns false if frame was missed and resync is needed
+    update(frame) {
+        if (!this.decoder.apply(frame)) {
+            return false;
+        }
+        var boards = this.decoder.state();
+        this.display_player.update(boards[0]);
+        if (this.display_opponent && boards.length > 1) {
+            this.display_opponent.update(boards[1]);
+        }
+        this.display_others.forEach((display, i) => {
+            if (boards.length > i + 1) {
+                display.update(boards[i + 1]);
+            }
+        });
+        return true;
     }
 
+    // Connect to server with WebSocket, fall back to SSE if it's not available
     connect() {
-        // Connect to server
-        this.sse = new EventSource(this.url + '/sse');
+        if (!window.WebSocket) {
+            this.connectSse();
+            return;
+        }
+        const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+        const ws = new WebSocket(protocol + '//' + window.location.host + this.url + this.path + '/ws' + this.query);
+        var opened = false;
+        ws.onopen = () => {
+            opened = true;
+            this.ws = ws;
+        };
+        ws.onmessage = (event) => {
+            var message = JSON.parse(event.data);
+            if (message.type === 'state') {
+                if (!this.update(message)) {
+                    ws.send(JSON.stringify({ type: 'resync' }));
+                }
+            } else if (message.type === 'spectators' && this.onSpectators) {
+                this.onSpectators(message.count);
+            } else if (message.type === 'solo_status' && this.onSoloStatus) {
+                this.onSoloStatus(message.status);
+            } else if (message.type === 'solo_finished' && this.onSoloFinished) {
+                this.onSoloFinished(message.result);
+            } else if (message.type === 'lobby' && this.onLobby) {
+                this.onLobby(message.count);
+            } else if (message.type === 'battle' && this.onBattle) {
+                this.onBattle(message);
+            } else if (message.type === 'team' && this.onTeam) {
+                this.onTeam(message);
+            } else if (message.type === 'coop' && this.onCoop) {
+                this.onCoop(message);
+            } else if (message.type === 'phase' && this.onPhase) {
+                this.onPhase(message);
+            } else if (message.type === 'pause') {
+                this.updatePause(message);
+            } else if (message.type === 'match_event' && message.event.startsWith('opponent_')) {
+                if (this.onOpponentReconnecting) {
+                    this.onOpponentReconnecting(message.event === 'opponent_reconnecting');
+                }
+            } else if (message.type === 'match_event' && (message.event === 'kicked' || message.event === 'wait_timeout' || message.event === 'shutdown')) {
+                ws.close();
+            }
+        };
+        ws.onclose = () => {
+            this.ws = null;
+            // Connection was never established, use SSE instead
+            if (!opened) {
+                this.connectSse();
+            }
+        };
+    }
+
+    connectSse() {
+        this.sse = new EventSource(this.url + this.path + '/sse' + this.query);
         this.sse.addEventListener('message', (event) => {
-            var data = JSON.parse(event.data);
-            this.display_player.update(data.player);
-            this.display_opponent.update(data.opponent);
+            // Keep-alive message while waiting for opponent
+            if (event.data === 'foo') {
+                return;
+            }
+            var frame = JSON.parse(event.data);
+            // Frame was missed, reconnect to get a keyframe
+            if (!this.update(frame)) {
+                this.sse.close();
+                this.connectSse();
+            }
         });
+        // Session closed by moderator, don't reconnect
+        this.sse.addEventListener('kicked', (event) => {
+            this.sse.close();
+        });
+        // No opponent found in time, don't reconnect
+        this.sse.addEventListener('wait_timeout', (event) => {
+            this.sse.close();
+        });
+        // Server is stopping, EventSource would keep reconnecting
+        this.sse.addEventListener('shutdown', (event) => {
+            this.sse.close();
+        });
+        // Spectated match is finished
+        this.sse.addEventListener('match_ended', (event) => {
+            this.sse.close();
+        });
+        this.sse.addEventListener('opponent_reconnecting', (event) => {
+            if (this.onOpponentReconnecting) {
+                this.onOpponentReconnecting(true);
+            }
+        });
+        this.sse.addEventListener('opponent_returned', (event) => {
+            if (this.onOpponentReconnecting) {
+                this.onOpponentReconnecting(false);
+            }
+        });
+        this.sse.addEventListener('lobby', (event) => {
+            if (this.onLobby) {
+                this.onLobby(Number(event.data));
+            }
+        });
+        this.sse.addEventListener('battle', (event) => {
+            if (this.onBattle) {
+                this.onBattle(JSON.parse(event.data));
+            }
+        });
+        this.sse.addEventListener('team', (event) => {
+            if (this.onTeam) {
+                this.onTeam(JSON.parse(event.data));
+            }
+        });
+        this.sse.addEventListener('coop', (event) => {
+            if (this.onCoop) {
+                this.onCoop(JSON.parse(event.data));
+            }
+        });
+        this.sse.addEventListener('phase', (event) => {
+            if (this.onPhase) {
+                this.onPhase(JSON.parse(event.data));
+            }
+        });
+        this.sse.addEventListener('pause', (event) => {
+            this.updatePause(JSON.parse(event.data));
+        });
+        this.sse.addEventListener('spectators', (event) => {
+            if (this.onSpectators) {
+                this.onSpectators(Number(event.data));
+            }
+        });
+        this.sse.addEventListener('status', (event) => {
+            if (this.onSoloStatus) {
+                this.onSoloStatus(JSON.parse(event.data));
+            }
+        });
+        // Single player game is finished, don't reconnect
+        this.sse.addEventListener('finished', (event) => {
+            this.sse.close();
+            if (this.onSoloFinished) {
+                this.onSoloFinished(JSON.parse(event.data));
+            }
+        });
+    }
+
+    // Send action over WebSocket if connected, otherwise POST it to /action
+    send(action) {
+        if (this.ws) {
+            this.seq += 1;
+            this.ws.send(JSON.stringify({ type: 'input', seq: this.seq, action: action }));
+        } else {
+            window.fetch(this.url + '/action', {
+                method: 'POST',
+                headers: { 'Content-Type': 'application/json' },
+                body: JSON.stringify([{ action: action, time: Date.now() }]),
+            });
+        }
+    }
+
+    updatePause(info) {
+        this.pauseInfo = info;
+        if (this.onPause) {
+            this.onPause(info);
+        }
+    }
+
+    // Human readable match phase, empty while the match is playing
+    static phaseText(info) {
+        switch (info.phase) {
+            case 'matched':
+                return 'Opponent found, connecting...';
+            case 'ready_check':
+                if (!info.player_ready) {
+                    return 'Press Enter when ready';
+                }
+                return info.opponent_ready ? '' : 'Waiting for opponent to get ready';
+            case 'countdown':
+                return 'Starting in ' + Math.ceil(info.countdown_ms / 1000) + 's';
+            case 'finished':
+                return 'Match finished';
+            default:
+                return '';
+        }
+    }
+
+    // Choose targeting in battle royale: random, attackers, kos or badges
+    target(targeting) {
+        if (this.ws) {
+            this.ws.send(JSON.stringify({ type: 'target', targeting: targeting }));
+        } else {
+            window.fetch(this.url + '/battle/target/' + targeting, { method: 'POST' });
+        }
+    }
+
+    // Confirm being ready to start the match
+    ready() {
+        if (this.ws) {
+            this.ws.send(JSON.stringify({ type: 'ready' }));
+        } else {
+            window.fetch(this.url + '/ready', { method: 'POST' });
+        }
+    }
+
+    // Human readable pause status, empty while the game is running
+    static pauseText(info) {
+        switch (info.status) {
+            case 'requested_by_player':
+                return 'Waiting for opponent to accept pause (P to cancel)';
+            case 'requested_by_opponent':
+                return 'Opponent requests pause (P to accept)';
+            case 'paused':
+                return 'Paused, ' + info.pauses_left + ' left (P to resume)';
+            case 'countdown':
+                return 'Resuming in ' + Math.ceil(info.countdown_ms / 1000) + 's';
+            default:
+                return '';
+        }
+    }
+
+    // Send pause or resume request over WebSocket if connected, otherwise POST it
+    sendPause(request) {
+        if (this.ws) {
+            this.ws.send(JSON.stringify({ type: request }));
+        } else {
+            window.fetch(this.url + '/' + request, { method: 'POST' });
+        }
+    }
+
+    pause() {
+        this.sendPause('pause');
+    }
+
+    resume() {
+        this.sendPause('resume');
+    }
+
+    // Resume paused game or withdraw own pause request, otherwise request pause
+    togglePause() {
+        const status = this.pauseInfo ? this.pauseInfo.status : 'running';
+        if (status === 'paused' || status === 'requested_by_player') {
+            this.resume();
+        } else {
+            this.pause();
+        }
     }
 
-    // Commands to send to server (to be implemented later)
     down() {
-        window.fetch(this.url + '/down', { method: 'POST' });
+        this.send('MoveDown');
     }
 
     moveLeft() {
-        window.fetch(this.url + '/left', { method: 'POST' });
+        this.send('MoveLeft');
     }
 
     moveRight() {
-        window.fetch(this.url + '/right', { method: 'POST' });
+        this.send('MoveRight');
     }
 
     rotateLeft() {
-        window.fetch(this.url + '/rotate_left', { method: 'POST' });
+        this.send('RotateLeft');
     }
 
     rotateRight() {
-        window.fetch(this.url + '/rotate_right', { method: 'POST' });
+        this.send('RotateRight');
     }
 
     drop() {
-        window.fetch(this.url + '/drop', { method: 'POST' });
-    }
-
-    bottom_refill() {
-        window.fetch(this.url + '/bottom_refill', { method: 'POST' });
+        this.send('Drop');
     }
 
     bindButtons(left_id, rotate_left_id, down_id, rotate_right_id, right_id) {
@@ -212,8 +547,11 @@ class TetrisClient {
                 case " ":
                     self.drop();
                     break;
+                case "p":
+                    self.togglePause();
+                    break;
                 case "Enter":
-                    self.bottom_refill();
+                    self.ready();
                     break;
             }
         }
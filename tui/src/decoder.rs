@@ -0,0 +1,165 @@
+use serde::Deserialize;
+use serde_json::Value;
+use tetris_engine::CellType;
+
+//
+// Restores boards state from keyframes and delta frames sent by server,
+// same as TetrisFrameDecoder in the browser client
+//
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Board {
+    pub cols: usize,
+    pub rows: usize,
+    pub field: Vec<Vec<CellType>>,
+    pub piece: Option<Piece>,
+    pub preview: Vec<Vec<CellType>>,
+    pub game_over: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Piece {
+    pub cell_type: CellType,
+    pub cells: Vec<(isize, isize)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BoardDelta {
+    #[serde(default)]
+    cells: Vec<(usize, usize, CellType)>,
+    // Absent if not changed, null if piece was fixed
+    #[serde(default, deserialize_with = "present")]
+    piece: Option<Option<Piece>>,
+    preview: Option<Vec<Vec<CellType>>>,
+    game_over: Option<bool>,
+}
+
+// Tell absent field from null one
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "frame", rename_all = "snake_case")]
+enum Frame {
+    Key { seq: u64, boards: Vec<Board> },
+    Delta { seq: u64, boards: Vec<BoardDelta> },
+}
+
+#[derive(Default)]
+pub struct FrameDecoder {
+    seq: Option<u64>,
+    boards: Vec<Board>,
+}
+
+impl FrameDecoder {
+    // Apply frame. Returns false if frame can't be applied and resync is needed
+    pub fn apply(&mut self, frame: Value) -> bool {
+        match serde_json::from_value(frame) {
+            Ok(Frame::Key { seq, boards }) => {
+                self.boards = boards;
+                self.seq = Some(seq);
+                true
+            }
+            Ok(Frame::Delta { seq, boards })
+                if self.seq.is_some_and(|last| seq == last + 1)
+                    && boards.len() == self.boards.len() =>
+            {
+                for (board, delta) in self.boards.iter_mut().zip(boards) {
+                    apply_delta(board, delta);
+                }
+                self.seq = Some(seq);
+                true
+            }
+            _ => {
+                self.seq = None;
+                false
+            }
+        }
+    }
+
+    // Boards for display with falling piece drawn on the field, player's own board goes first
+    pub fn state(&self) -> Vec<Board> {
+        self.boards.iter().map(compose).collect()
+    }
+}
+
+fn apply_delta(board: &mut Board, delta: BoardDelta) {
+    for (x, y, cell) in delta.cells {
+        if let Some(row) = board.field.get_mut(y) {
+            if let Some(field_cell) = row.get_mut(x) {
+                *field_cell = cell;
+            }
+        }
+    }
+    if let Some(piece) = delta.piece {
+        board.piece = piece;
+    }
+    if let Some(preview) = delta.preview {
+        board.preview = preview;
+    }
+    if let Some(game_over) = delta.game_over {
+        board.game_over = game_over;
+    }
+}
+
+fn compose(board: &Board) -> Board {
+    let mut board = board.clone();
+    if let Some(piece) = board.piece.take() {
+        for (x, y) in piece.cells {
+            if let Some(cell) = board
+                .field
+                .get_mut(y as usize)
+                .and_then(|row| row.get_mut(x as usize))
+            {
+                *cell = piece.cell_type;
+            }
+        }
+    }
+    board
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Keyframe and delta as sent by the server when the piece is fixed and the next one is shown
+    #[test]
+    fn delta_reproduces_next_keyframe() {
+        let mut decoder = FrameDecoder::default();
+        assert!(decoder.apply(json!({"frame": "key", "seq": 0, "boards": [{
+            "cols": 2, "rows": 2,
+            "field": [[0, 0], [0, 0]],
+            "piece": {"cell_type": 5, "cells": [[0, 1], [1, 1]]},
+            "preview": [[3, 0], [3, 3]],
+            "game_over": false
+        }]})));
+        assert!(
+            decoder.apply(json!({"frame": "delta", "seq": 1, "boards": [{
+                "cells": [[0, 1, 5], [1, 1, 5]],
+                "piece": null,
+                "preview": [[4, 4], [0, 0]]
+            }]}))
+        );
+        let mut next = FrameDecoder::default();
+        assert!(next.apply(json!({"frame": "key", "seq": 1, "boards": [{
+            "cols": 2, "rows": 2,
+            "field": [[0, 0], [5, 5]],
+            "piece": null,
+            "preview": [[4, 4], [0, 0]],
+            "game_over": false
+        }]})));
+        let (board, expected) = (&decoder.boards[0], &next.boards[0]);
+        assert_eq!(board.field, expected.field);
+        assert!(board.piece.is_none());
+        assert_eq!(board.preview, expected.preview);
+        assert_eq!(board.game_over, expected.game_over);
+        // Frame after a missed one needs a resync
+        assert!(!decoder.apply(json!({"frame": "delta", "seq": 3, "boards": [{}]})));
+    }
+}
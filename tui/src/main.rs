@@ -0,0 +1,239 @@
+// Terminal client. Plays over the server's WebSocket endpoint, renders boards
+// from state frames and sends key presses as actions.
+//
+// Usage: tetris-tui [--server HOST:PORT] [--user-id ID] [--solo MODE | --spectate MATCH_ID]
+//
+// Keys: arrows or WASD to move and rotate, space to drop, Enter when ready,
+// p to pause or resume, q or Esc to quit
+
+mod decoder;
+mod render;
+
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, terminal};
+use serde_json::{json, Value};
+use tetris_engine::Action;
+use tungstenite::handshake::client::generate_key;
+use tungstenite::http::Request;
+use tungstenite::{Message, WebSocket};
+
+use decoder::FrameDecoder;
+
+// Time to wait for server messages and key presses in each loop iteration
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+struct Settings {
+    server: String,
+    user_id: Option<u32>,
+    // Stream path, e.g. /solo/sprint or /matches/1. Empty for a match
+    path: String,
+    spectator: bool,
+}
+
+impl Settings {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut settings = Settings {
+            server: "127.0.0.1:8000".to_string(),
+            user_id: None,
+            path: String::new(),
+            spectator: false,
+        };
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Missing value for {}", arg))?;
+            match arg.as_str() {
+                "--server" => settings.server = value,
+                "--user-id" => {
+                    let user_id = value
+                        .parse()
+                        .map_err(|_| format!("Invalid user id: {}", value))?;
+                    settings.user_id = Some(user_id);
+                }
+                "--solo" => settings.path = format!("/solo/{}", value),
+                "--spectate" => {
+                    settings.path = format!("/matches/{}", value);
+                    settings.spectator = true;
+                }
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+// Connect to the server, user id is passed as cookie to continue playing as the same user
+fn connect(settings: &Settings) -> Result<WebSocket<TcpStream>, String> {
+    let url = format!("ws://{}{}/ws", settings.server, settings.path);
+    let mut request = Request::builder()
+        .uri(&url)
+        .header("Host", &settings.server)
+        .header("Connection", "Upgrade")
+        .header("Upgrade", "websocket")
+        .header("Sec-WebSocket-Version", "13")
+        .header("Sec-WebSocket-Key", generate_key());
+    if let Some(user_id) = settings.user_id {
+        request = request.header("Cookie", format!("user_id={}", user_id));
+    }
+    let request = request.body(()).map_err(|e| e.to_string())?;
+    let stream = TcpStream::connect(&settings.server).map_err(|e| e.to_string())?;
+    let (socket, _) = tungstenite::client(request, stream).map_err(|e| e.to_string())?;
+    socket
+        .get_ref()
+        .set_read_timeout(Some(POLL_INTERVAL))
+        .map_err(|e| e.to_string())?;
+    Ok(socket)
+}
+
+fn key_action(code: KeyCode) -> Option<Action> {
+    match code {
+        KeyCode::Left | KeyCode::Char('a') => Some(Action::MoveLeft),
+        KeyCode::Right | KeyCode::Char('d') => Some(Action::MoveRight),
+        KeyCode::Up | KeyCode::Char('w') => Some(Action::RotateLeft),
+        KeyCode::Down | KeyCode::Char('s') => Some(Action::MoveDown),
+        KeyCode::Char('e') => Some(Action::RotateRight),
+        KeyCode::Char(' ') => Some(Action::Drop),
+        _ => None,
+    }
+}
+
+// Human readable line for non-state server message
+fn status_line(message: &Value) -> Option<String> {
+    match message["type"].as_str()? {
+        "match_event" => Some(format!("Match: {}", message["event"].as_str()?)),
+        "spectators" => Some(format!("Spectators: {}", message["count"])),
+        "solo_status" => {
+            let status = &message["status"];
+            Some(format!(
+                "Lines: {}  Level: {}  Score: {}",
+                status["lines"], status["level"], status["score"]
+            ))
+        }
+        "solo_finished" => Some(format!("Finished: {}", message["result"])),
+        "phase" => Some(format!(
+            "Match: {}  Ready: {}  Opponent ready: {}",
+            message["phase"].as_str()?,
+            message["player_ready"],
+            message["opponent_ready"]
+        )),
+        "pause" => Some(format!(
+            "Pause: {}  Pauses left: {}",
+            message["status"].as_str()?,
+            message["pauses_left"]
+        )),
+        _ => None,
+    }
+}
+
+fn run(socket: &mut WebSocket<TcpStream>, settings: &Settings) -> Result<(), String> {
+    let mut out = io::stdout();
+    let mut decoder = FrameDecoder::default();
+    let mut status = "Connecting".to_string();
+    let mut seq = 0;
+    // Pause status from the last pause message, 'p' key resumes or requests pause
+    let mut pause_status = "running".to_string();
+    let mut redraw = true;
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let message: Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+                // Spectator stream sends bare frames, player's stream wraps them in state messages
+                let is_frame = message["type"] == "state" || message.get("frame").is_some();
+                if is_frame {
+                    if !decoder.apply(message) {
+                        socket
+                            .send(Message::Text(json!({"type": "resync"}).to_string()))
+                            .map_err(|e| e.to_string())?;
+                    }
+                } else if let Some(line) = status_line(&message) {
+                    if let Some(pause) = message["status"]
+                        .as_str()
+                        .filter(|_| message["type"] == "pause")
+                    {
+                        pause_status = pause.to_string();
+                    }
+                    status = line;
+                }
+                redraw = true;
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        }
+        while event::poll(Duration::ZERO).map_err(|e| e.to_string())? {
+            let Event::Key(key) = event::read().map_err(|e| e.to_string())? else {
+                continue;
+            };
+            if key.kind == KeyEventKind::Release {
+                continue;
+            }
+            if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                return Ok(());
+            }
+            if key.code == KeyCode::Enter && !settings.spectator {
+                socket
+                    .send(Message::Text(json!({"type": "ready"}).to_string()))
+                    .map_err(|e| e.to_string())?;
+            }
+            if key.code == KeyCode::Char('p') && !settings.spectator {
+                let request = match pause_status.as_str() {
+                    "paused" | "requested_by_player" => "resume",
+                    _ => "pause",
+                };
+                socket
+                    .send(Message::Text(json!({"type": request}).to_string()))
+                    .map_err(|e| e.to_string())?;
+            }
+            if let Some(action) = key_action(key.code).filter(|_| !settings.spectator) {
+                seq += 1;
+                let input = json!({"type": "input", "seq": seq, "action": action});
+                socket
+                    .send(Message::Text(input.to_string()))
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        if redraw {
+            render::draw(&mut out, &decoder.state(), &status).map_err(|e| e.to_string())?;
+            redraw = false;
+        }
+    }
+}
+
+fn main() {
+    let settings = match Settings::from_args(std::env::args().skip(1)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut socket = match connect(&settings) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("Failed to connect to {}: {}", settings.server, e);
+            std::process::exit(1);
+        }
+    };
+
+    // Terminal is restored before reporting the result
+    let setup = terminal::enable_raw_mode()
+        .and_then(|_| execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide));
+    let result = setup
+        .map_err(|e| e.to_string())
+        .and_then(|_| run(&mut socket, &settings));
+    let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    let _ = socket.close(None);
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
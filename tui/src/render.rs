@@ -0,0 +1,107 @@
+use std::io::{self, Write};
+
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor};
+use crossterm::{cursor, queue, terminal};
+use tetris_engine::CellType;
+
+use crate::decoder::Board;
+
+// Columns between boards
+const BOARD_GAP: u16 = 4;
+
+// Same colors as in the browser client
+fn cell_color(cell: CellType) -> Color {
+    match cell {
+        CellType::Empty => Color::Rgb {
+            r: 0x20,
+            g: 0x20,
+            b: 0x20,
+        },
+        CellType::Blasted => Color::White,
+        CellType::I => Color::Rgb {
+            r: 0x00,
+            g: 0xff,
+            b: 0xff,
+        },
+        CellType::J => Color::Rgb {
+            r: 0x00,
+            g: 0x00,
+            b: 0xff,
+        },
+        CellType::L => Color::Rgb {
+            r: 0xff,
+            g: 0xa5,
+            b: 0x00,
+        },
+        CellType::O => Color::Rgb {
+            r: 0xff,
+            g: 0xff,
+            b: 0x00,
+        },
+        CellType::S => Color::Rgb {
+            r: 0x00,
+            g: 0xff,
+            b: 0x00,
+        },
+        CellType::T => Color::Rgb {
+            r: 0x80,
+            g: 0x00,
+            b: 0x80,
+        },
+        CellType::Z => Color::Rgb {
+            r: 0xff,
+            g: 0x00,
+            b: 0x00,
+        },
+    }
+}
+
+// Draw boards side by side with walls, each cell is two characters wide.
+// Status line goes below the boards. Screen is not cleared to avoid flicker
+pub fn draw(out: &mut impl Write, boards: &[Board], status: &str) -> io::Result<()> {
+    let mut left = 0;
+    for board in boards {
+        draw_board(out, board, left)?;
+        left += (board.cols as u16 + 2) * 2 + BOARD_GAP;
+    }
+    let bottom = boards.iter().map(|board| board.rows).max().unwrap_or(0) as u16 + 2;
+    queue!(
+        out,
+        cursor::MoveTo(0, bottom),
+        ResetColor,
+        Print(status),
+        terminal::Clear(terminal::ClearType::UntilNewLine)
+    )?;
+    out.flush()
+}
+
+fn draw_board(out: &mut impl Write, board: &Board, left: u16) -> io::Result<()> {
+    let wall = Color::DarkGrey;
+    for (y, row) in board.field.iter().enumerate() {
+        queue!(
+            out,
+            cursor::MoveTo(left, y as u16),
+            SetBackgroundColor(wall),
+            Print("  ")
+        )?;
+        for cell in row {
+            queue!(out, SetBackgroundColor(cell_color(*cell)), Print("  "))?;
+        }
+        queue!(out, SetBackgroundColor(wall), Print("  "))?;
+    }
+    queue!(
+        out,
+        cursor::MoveTo(left, board.rows as u16),
+        SetBackgroundColor(wall),
+        Print(" ".repeat((board.cols + 2) * 2)),
+        ResetColor
+    )?;
+    if board.game_over {
+        queue!(
+            out,
+            cursor::MoveTo(left + 2, board.rows as u16 / 2),
+            Print(" GAME OVER ")
+        )?;
+    }
+    Ok(())
+}